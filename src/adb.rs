@@ -1,21 +1,47 @@
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::io;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 use std::thread::sleep;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use image::DynamicImage;
+use image::{DynamicImage, RgbaImage};
+use tracing::{debug, debug_span, warn};
 use which::which;
 
-use crate::error::{
-    AdbError,
-    CaptureScreenError,
-    DisplaySizeError,
-    ScreenSumError,
-};
+use crate::adb::display::parse_wm_size;
+use crate::diff::{diff_images, DiffOptions, ImageDiff};
+use crate::device::{parse_devices, Device, DeviceState, WaitState};
+use crate::error::AdbError;
+use crate::frames::{FrameStream, FrameStreamOptions};
+use crate::geometry::Rect;
 use crate::key_code::KeyCode;
-use crate::process::Process;
+use crate::perceptual::{perceptual_hash, HashAlgorithm, PerceptualHash};
+use crate::process::ProcessRunner;
+use crate::runner::{CancellationToken, CommandOutput, CommandRunner, RunOptions};
+use crate::screencap::{parse_raw_screencap, CaptureMode, RawScreencap};
+use crate::server::{default_server_addr, server_failure, ServerRunner};
+use crate::tracker::DeviceTracker;
+use crate::template::{find_template, MatchOptions, TemplateMatch};
+use crate::sync::{RemoteDirEntry, RemoteStat, SyncConnection, SyncProgress};
 use crate::utils;
 use crate::utils::rand_rng;
 
+pub(crate) mod properties;
+pub(crate) mod display;
+mod probes;
+mod reboot;
+pub(crate) mod rotation;
+pub(crate) mod screenrecord;
+mod stability;
+pub(crate) mod touchscreen;
+mod wireless;
+
+const REDACTED_ARGUMENT: &str = "<redacted>";
+pub(crate) const WAIT_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct DisplaySize {
     pub width: u32,
     pub height: u32,
@@ -23,202 +49,461 @@ pub struct DisplaySize {
 
 #[derive(Clone, Debug)]
 pub struct Adb {
-    path_buf: PathBuf,
+    runner: Arc<dyn CommandRunner>,
     target_serial: Option<String>,
+    run_options: RunOptions,
+    redact_arguments: bool,
+    server_addr: SocketAddr,
+    properties: Arc<Mutex<Option<HashMap<String, String>>>>,
+    capture_mode: CaptureMode,
+    similarity_threshold: f64,
 }
 
 impl Adb {
     pub fn new(
         adb_path: Option<PathBuf>, target_serial: Option<String>,
     ) -> Result<Self, AdbError> {
-        let adb_path_buf = match adb_path {
-            Some(provided_adb_path) => provided_adb_path,
-            None => match which("adb") {
-                Ok(found_adb_path) => found_adb_path,
-                Err(_) => {
-                    return Err(AdbError::AdbNotFound);
-                }
-            },
-        };
-        if !adb_path_buf.exists() {
-            return Err(AdbError::AdbNotFound);
-        }
-        let adb = Adb {
-            path_buf: adb_path_buf,
-            target_serial: target_serial.clone(),
-        };
+        Self::with_runner(
+            Arc::new(ProcessRunner::new(resolve_adb_path(adb_path)?)),
+            target_serial,
+        )
+    }
+
+    /// Like [`Adb::new`], but waits up to `timeout` for the target to come online instead of
+    /// failing straight away, e.g. right after a reboot or reconnect.
+    pub fn new_when_ready(
+        adb_path: Option<PathBuf>, target_serial: Option<String>, timeout: Duration,
+    ) -> Result<Self, AdbError> {
+        Self::with_runner_when_ready(
+            Arc::new(ProcessRunner::new(resolve_adb_path(adb_path)?)),
+            target_serial,
+            timeout,
+        )
+    }
+
+    /// Connects through the adb server smart-socket protocol at `server_addr` instead of
+    /// spawning the adb binary, see [`ServerRunner`].
+    pub fn with_server(
+        server_addr: SocketAddr, target_serial: Option<String>,
+    ) -> Result<Self, AdbError> {
+        Self::with_runner(
+            Arc::new(ServerRunner::new(server_addr)),
+            target_serial,
+        ).map(|adb| adb.with_server_addr(server_addr))
+    }
+
+    pub fn with_runner(
+        runner: Arc<dyn CommandRunner>, target_serial: Option<String>,
+    ) -> Result<Self, AdbError> {
+        let adb = Self::unchecked(runner, target_serial.clone());
         match target_serial {
             Some(provided_target_serial) => {
-                if !adb.have_target(&provided_target_serial) {
+                if !adb.have_target(&provided_target_serial)? {
                     return Err(AdbError::TargetNotOnline);
                 }
             }
             None => {
-                if !adb.have_active_target() {
+                if !adb.have_active_target()? {
                     return Err(AdbError::NoExclusiveTargetOnline);
                 }
             }
         }
-        adb.start_server();
+        adb.start_server()?;
+        Ok(adb)
+    }
+
+    /// Creates an untargeted `Adb` for server-level commands such as
+    /// [`Adb::list_devices`] or [`Adb::connect`], without requiring any device to be online.
+    pub fn host(adb_path: Option<PathBuf>) -> Result<Self, AdbError> {
+        Self::host_with_runner(Arc::new(ProcessRunner::new(resolve_adb_path(adb_path)?)))
+    }
+
+    pub fn host_with_runner(runner: Arc<dyn CommandRunner>) -> Result<Self, AdbError> {
+        let adb = Self::unchecked(runner, None);
+        adb.start_server()?;
         Ok(adb)
     }
 
-    fn adb_no_target(&self, args: &[&str]) -> Option<(String, String)> {
-        Process::exec(self.path_buf.to_string_lossy().as_ref(), args)
+    pub fn with_runner_when_ready(
+        runner: Arc<dyn CommandRunner>, target_serial: Option<String>, timeout: Duration,
+    ) -> Result<Self, AdbError> {
+        let adb = Self::unchecked(runner, target_serial);
+        adb.start_server()?;
+        adb.wait_for_state(WaitState::Device, timeout)?;
+        Ok(adb)
+    }
+
+    fn unchecked(runner: Arc<dyn CommandRunner>, target_serial: Option<String>) -> Self {
+        Adb {
+            runner,
+            target_serial,
+            run_options: RunOptions::default(),
+            redact_arguments: true,
+            server_addr: default_server_addr(),
+            properties: Arc::new(Mutex::new(None)),
+            capture_mode: CaptureMode::default(),
+            similarity_threshold: stability::DEFAULT_SIMILARITY_THRESHOLD,
+        }
+    }
+
+    pub fn target_serial(&self) -> Option<&str> {
+        self.target_serial.as_deref()
+    }
+
+    /// Returns a copy of this `Adb` whose commands are killed after `timeout`.
+    ///
+    /// Use it to set the default for an instance, or inline for a single call:
+    /// `adb.with_timeout(Duration::from_secs(5)).capture_screen_as_bytes()`.
+    pub fn with_timeout(&self, timeout: Duration) -> Adb {
+        let mut adb = self.clone();
+        adb.run_options.timeout = Some(timeout);
+        adb
+    }
+
+    pub fn without_timeout(&self) -> Adb {
+        let mut adb = self.clone();
+        adb.run_options.timeout = None;
+        adb
+    }
+
+    /// Returns a copy of this `Adb` whose commands are aborted once `cancellation` is
+    /// cancelled, typically from another thread.
+    pub fn with_cancellation(&self, cancellation: CancellationToken) -> Adb {
+        let mut adb = self.clone();
+        adb.run_options.cancellation = Some(cancellation);
+        adb
+    }
+
+    /// Returns a copy of this `Adb` where `enabled` controls whether sensitive arguments, such
+    /// as [`Adb::input_text`] payloads, are redacted from trace output. Redaction is on by
+    /// default.
+    pub fn with_argument_redaction(&self, enabled: bool) -> Adb {
+        let mut adb = self.clone();
+        adb.redact_arguments = enabled;
+        adb
+    }
+
+    /// Returns a copy of this `Adb` that opens direct server connections, used for file
+    /// transfers, to `server_addr`. The copy does not share the cached
+    /// [`Adb::properties`].
+    pub fn with_server_addr(&self, server_addr: SocketAddr) -> Adb {
+        let mut adb = self.clone();
+        adb.server_addr = server_addr;
+        adb.properties = Arc::new(Mutex::new(None));
+        adb
+    }
+
+    /// Returns a copy of this `Adb` that captures screenshots in `capture_mode`, either as the
+    /// instance default or inline for a single call, like [`Adb::with_timeout`].
+    pub fn with_capture_mode(&self, capture_mode: CaptureMode) -> Adb {
+        let mut adb = self.clone();
+        adb.capture_mode = capture_mode;
+        adb
+    }
+
+    pub fn timeout(&self) -> Option<Duration> {
+        self.run_options.timeout
+    }
+
+    fn adb_no_target(&self, args: &[&str]) -> Result<CommandOutput, AdbError> {
+        self.exec(args, &[])
+    }
+
+    fn adb_target(&self, args: &[&str]) -> Result<CommandOutput, AdbError> {
+        self.adb_target_redacted(args, &[])
     }
 
-    fn adb_no_target_as_bytes(&self, args: &[&str]) -> Option<Vec<u8>> {
-        Process::exec_and_return_stdout_bytes(
-            self.path_buf.to_string_lossy().as_ref(), args,
+    /// Runs a targeted command whose arguments at the `sensitive` indices are redacted from
+    /// trace output unless redaction was disabled with [`Adb::with_argument_redaction`].
+    fn adb_target_redacted(
+        &self, args: &[&str], sensitive: &[usize],
+    ) -> Result<CommandOutput, AdbError> {
+        let target_args = self.target_args(args);
+        let offset = target_args.len() - args.len();
+        self.exec(
+            &target_args,
+            &sensitive.iter().map(|index| index + offset).collect::<Vec<_>>(),
         )
     }
 
-    fn adb_target(&self, args: &[&str]) -> Option<(String, String)> {
+    /// Prefixes `args` with `-s <serial>` when this instance has a target.
+    fn target_args<'a>(&'a self, args: &[&'a str]) -> Vec<&'a str> {
         match &self.target_serial {
-            Some(target) => {
-                self.adb_no_target(
-                    &["-s", target]
-                        .iter()
-                        .cloned()
-                        .chain(args.iter().cloned())
-                        .collect::<Vec<_>>()
-                )
-            }
-            None => {
-                self.adb_no_target(args)
-            }
+            Some(target) => ["-s", target.as_str()]
+                .iter()
+                .cloned()
+                .chain(args.iter().cloned())
+                .collect(),
+            None => args.to_vec(),
         }
     }
 
-    fn adb_target_as_bytes(&self, args: &[&str]) -> Option<Vec<u8>> {
-        match &self.target_serial {
-            Some(target) => {
-                self.adb_no_target_as_bytes(
-                    &["-s", target]
-                        .iter()
-                        .cloned()
-                        .chain(args.iter().cloned())
-                        .collect::<Vec<_>>()
-                )
+    fn exec(&self, args: &[&str], sensitive: &[usize]) -> Result<CommandOutput, AdbError> {
+        self.exec_unchecked(args, sensitive).and_then(check_output)
+    }
+
+    /// Runs a command without turning its exit status or stderr into errors, for commands
+    /// whose outcome has to be parsed from their output.
+    fn exec_unchecked(
+        &self, args: &[&str], sensitive: &[usize],
+    ) -> Result<CommandOutput, AdbError> {
+        let span = debug_span!(
+            "adb",
+            serial = self.target_serial.as_deref().unwrap_or_default(),
+            args = %self.loggable_args(args, sensitive),
+        );
+        let _entered = span.enter();
+        match self.runner.run(args, &self.run_options) {
+            Ok(output) => {
+                debug!(
+                    exit_code = ?output.exit_code,
+                    duration_ms = output.duration.as_millis() as u64,
+                    stdout_bytes = output.stdout.len(),
+                    stderr_bytes = output.stderr.len(),
+                    "adb command finished",
+                );
+                Ok(output)
             }
-            None => {
-                self.adb_no_target_as_bytes(args)
+            Err(err) => {
+                warn!(error = %err, "adb command did not complete");
+                Err(run_error(err, &self.run_options))
             }
         }
     }
 
-    pub fn restart_server(&self) {
-        self.kill_server();
-        self.start_server();
+    fn sync<T>(
+        &self, operation: impl FnOnce(&mut SyncConnection) -> io::Result<T>,
+    ) -> Result<T, AdbError> {
+        let span = debug_span!(
+            "adb_sync",
+            serial = self.target_serial.as_deref().unwrap_or_default(),
+        );
+        let _entered = span.enter();
+        SyncConnection::open(self.server_addr, self.target_serial(), &self.run_options)
+            .and_then(|mut connection| operation(&mut connection))
+            .map_err(|err| server_error(err, &self.run_options))
+    }
+
+    pub(crate) fn loggable_args(&self, args: &[&str], sensitive: &[usize]) -> String {
+        loggable_args(args, sensitive, self.redact_arguments)
+    }
+
+    pub fn restart_server(&self) -> Result<(), AdbError> {
+        self.kill_server()?;
+        self.start_server()?;
         sleep(Duration::from_millis(
             rand_rng(1000, 3000)
         ));
+        Ok(())
     }
 
-    pub fn kill_server(&self) {
-        self.adb_no_target(&["kill-server"]);
+    pub fn kill_server(&self) -> Result<(), AdbError> {
+        self.adb_no_target(&["kill-server"]).map(|_| ())
     }
 
-    pub fn start_server(&self) {
-        self.adb_no_target(&["start-server"]);
+    pub fn start_server(&self) -> Result<(), AdbError> {
+        self.adb_no_target(&["start-server"]).map(|_| ())
     }
 
-    fn targets(&self) -> Option<(String, String)> {
-        self.adb_no_target(&["devices"])
+    /// Lists every device known to the adb server, parsed from `adb devices -l`.
+    pub fn list_devices(&self) -> Result<Vec<Device>, AdbError> {
+        self.adb_no_target(&["devices", "-l"])
+            .map(|output| parse_devices(&output.stdout_string()))
     }
 
-    pub(crate) fn have_active_target(&self) -> bool {
-        match self.targets() {
-            Some((output, _)) => {
-                let matched_lines = output.lines().filter(
-                    |line| line.trim().ends_with("device") && line.split_whitespace().count() > 1
-                );
-                matched_lines.take(2).count() == 1
-            }
-            None => false
-        }
+    /// Subscribes to hotplug events over a dedicated adb server connection, see
+    /// [`DeviceTracker`].
+    pub fn track_devices(&self) -> Result<DeviceTracker, AdbError> {
+        DeviceTracker::start(self.server_addr)
     }
 
-    pub fn have_target(&self, target: &str) -> bool {
-        self.targets().map_or(
-            false, |output| output.0.contains(target),
-        )
+    /// Captures the screen continuously on a background thread, see [`FrameStream`].
+    pub fn frame_stream(&self, options: FrameStreamOptions) -> Result<FrameStream, AdbError> {
+        FrameStream::start(self, options)
+    }
+
+    pub fn have_active_target(&self) -> Result<bool, AdbError> {
+        Ok(has_exclusive_active_target(&self.list_devices()?))
     }
 
-    pub fn display_size(&self) -> Result<DisplaySize, DisplaySizeError> {
-        if let Some((stdin_output, _)) = self.adb_target(&["shell", "wm", "size"]) {
-            let size_as_string: String = stdin_output
-                .chars()
-                .filter(|&c| c.is_ascii_digit() || c == 'x')
-                .collect();
-
-            let mut split_iter = size_as_string.split('x');
-            if let (Some(width_str), Some(height_str)) = (
-                split_iter.next(), split_iter.next()
-            ) {
-                if let (Ok(width), Ok(height)) = (
-                    width_str.parse::<u32>(), height_str.parse::<u32>()
-                ) {
-                    return Ok(DisplaySize { width, height });
+    /// Whether a device with exactly the serial `target` is listed, in any state.
+    pub fn have_target(&self, target: &str) -> Result<bool, AdbError> {
+        Ok(has_target(&self.list_devices()?, target))
+    }
+
+    /// Polls the device list until the target reaches `state`, or until it is gone for
+    /// [`WaitState::Disconnect`]. Without a target serial, waits for exactly one online device
+    /// for [`WaitState::Device`] and for any device in the other states.
+    pub fn wait_for_state(&self, state: WaitState, timeout: Duration) -> Result<(), AdbError> {
+        self.poll_until(timeout, || {
+            let devices = self.list_devices()?;
+            let reached = match (&self.target_serial, state.device_state()) {
+                (Some(serial), None) => !has_target(&devices, serial),
+                (Some(serial), Some(device_state)) => devices.iter().any(|device| {
+                    &device.serial == serial && device.state == device_state
+                }),
+                (None, None) => devices.is_empty(),
+                (None, Some(DeviceState::Device)) => has_exclusive_active_target(&devices),
+                (None, Some(device_state)) => {
+                    devices.iter().any(|device| device.state == device_state)
                 }
+            };
+            Ok(reached.then_some(()))
+        })
+    }
+
+    /// Waits for the target to come online and finish booting, i.e. `sys.boot_completed` is
+    /// `1` and the boot animation is no longer running.
+    pub fn wait_for_boot_completed(&self, timeout: Duration) -> Result<(), AdbError> {
+        let started = Instant::now();
+        self.wait_for_state(WaitState::Device, timeout)?;
+        self.poll_until(timeout.saturating_sub(started.elapsed()), || {
+            // The device may still drop off the bus while booting, so errors just mean "not yet".
+            let boot_completed = self.getprop("sys.boot_completed").unwrap_or_default();
+            let boot_animation = self.getprop("init.svc.bootanim").unwrap_or_default();
+            Ok((boot_completed == "1" && boot_animation != "running").then_some(()))
+        })
+    }
+
+    fn getprop(&self, name: &str) -> Result<String, AdbError> {
+        self.adb_target(&["shell", "getprop", name]).map(|output| output.stdout_string())
+    }
+
+    /// Calls `condition` every [`WAIT_POLL_INTERVAL`] until it yields a value, failing with
+    /// [`AdbError::Timeout`] after `timeout` or [`AdbError::Cancelled`] once this instance's
+    /// cancellation token is cancelled.
+    pub(crate) fn poll_until<T>(
+        &self,
+        timeout: Duration,
+        mut condition: impl FnMut() -> Result<Option<T>, AdbError>,
+    ) -> Result<T, AdbError> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if self.run_options.is_cancelled() {
+                return Err(AdbError::Cancelled);
             }
+            if let Some(value) = condition()? {
+                return Ok(value);
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(AdbError::Timeout(timeout));
+            }
+            sleep(WAIT_POLL_INTERVAL.min(deadline - now));
         }
-        Err(DisplaySizeError)
     }
 
-    pub fn capture_screen_as_bytes(&self) -> Result<Vec<u8>, CaptureScreenError> {
-        if let Some(output) = self.adb_target_as_bytes(
-            &["exec-out", "screencap", "-p"]
-        ) {
-            return Ok(output);
+    pub fn display_size(&self) -> Result<DisplaySize, AdbError> {
+        parse_display_size(self.adb_target(&["shell", "wm", "size"])?.stdout_string())
+    }
+
+    pub fn capture_screen_as_bytes(&self) -> Result<Vec<u8>, AdbError> {
+        self.adb_target(&["exec-out", "screencap", "-p"])
+            .map(|output| output.stdout)
+    }
+
+    /// Captures the screen in this instance's [`CaptureMode`], PNG unless changed with
+    /// [`Adb::with_capture_mode`].
+    pub fn capture_screen_as_rgba_image(&self) -> Result<RgbaImage, AdbError> {
+        let output = self.adb_target(self.capture_mode.args())?;
+        match self.capture_mode {
+            CaptureMode::Png => image::load_from_memory(&output.stdout)
+                .map(|image| image.into_rgba8())
+                .map_err(|err| AdbError::UnparseableOutput(err.to_string())),
+            CaptureMode::Raw => parse_raw_screencap(&output.stdout).map(|raw| raw.image),
         }
-        Err(CaptureScreenError)
     }
 
-    pub fn capture_screen_as_dynamic_image(&self) -> Result<DynamicImage, CaptureScreenError> {
-        let image_bytes = self.capture_screen_as_bytes()?;
-        image::load_from_memory(&image_bytes)
-            .map_err(|_| CaptureScreenError)
+    pub fn capture_screen_as_dynamic_image(&self) -> Result<DynamicImage, AdbError> {
+        self.capture_screen_as_rgba_image().map(DynamicImage::ImageRgba8)
+    }
+
+    /// Captures the raw framebuffer regardless of this instance's [`CaptureMode`], together
+    /// with the colorspace its pixel values are in, for color checks on wide gamut displays.
+    pub fn capture_raw_screencap(&self) -> Result<RawScreencap, AdbError> {
+        parse_raw_screencap(&self.adb_target(CaptureMode::Raw.args())?.stdout)
+    }
+
+    /// Captures the screen cropped to `region`, clipped to the screen bounds.
+    pub fn capture_region(&self, region: Rect) -> Result<DynamicImage, AdbError> {
+        let dynamic_image = self.capture_screen_as_dynamic_image()?;
+        let bounds = Rect::new(0, 0, dynamic_image.width(), dynamic_image.height());
+        let region = region.intersection(&bounds).ok_or(AdbError::RegionOutOfBounds(region))?;
+        Ok(dynamic_image.crop_imm(region.x, region.y, region.width, region.height))
+    }
+
+    /// Captures the screen and finds every occurrence of `template` on it, see
+    /// [`find_template`].
+    pub fn find_on_screen(
+        &self, template: &DynamicImage, options: &MatchOptions,
+    ) -> Result<Vec<TemplateMatch>, AdbError> {
+        let dynamic_image = self.capture_screen_as_dynamic_image()?;
+        Ok(find_template(&dynamic_image, template, options))
+    }
+
+    /// Like [`Adb::find_on_screen`], returning only the best match.
+    pub fn find_best_on_screen(
+        &self, template: &DynamicImage, options: &MatchOptions,
+    ) -> Result<Option<TemplateMatch>, AdbError> {
+        Ok(self.find_on_screen(template, options)?.into_iter().next())
+    }
+
+    /// Compares the screen against `baseline`, returning the capture along with the diff so
+    /// both can be saved with [`ImageDiff::save`].
+    pub fn diff_screen(
+        &self, baseline: &DynamicImage, options: &DiffOptions,
+    ) -> Result<(DynamicImage, ImageDiff), AdbError> {
+        let dynamic_image = self.capture_screen_as_dynamic_image()?;
+        let diff = diff_images(baseline, &dynamic_image, options);
+        Ok((dynamic_image, diff))
     }
 
-    pub fn capture_screen_as_file(&self, file_name: &str) -> Result<PathBuf, CaptureScreenError> {
+    pub fn capture_screen_as_file(&self, file_name: &str) -> Result<PathBuf, AdbError> {
         let dynamic_image = self.capture_screen_as_dynamic_image()?;
         utils::save_dynamic_image_as_png(&dynamic_image, file_name)
-            .map_err(|_| CaptureScreenError)
+            .map_err(AdbError::ImageSave)
     }
 
-    pub fn screen_sum(&self) -> Result<String, ScreenSumError> {
-        if let Ok(bytes) = self.capture_screen_as_bytes() {
-            return Ok(utils::md5_of_bytes(&bytes));
-        }
-        Err(ScreenSumError)
+    /// MD5 of the encoded screenshot, which changes with every pixel, status bar clock
+    /// included. Prefer [`Adb::screen_hash`] to compare screens.
+    pub fn screen_sum(&self) -> Result<String, AdbError> {
+        let bytes = self.capture_screen_as_bytes()?;
+        Ok(utils::md5_of_bytes(&bytes))
     }
 
-    pub fn start_activity(&self, activity_component_name: &str) -> Option<(String, String)> {
-        self.adb_target(&["shell", "am", "start", "-n", activity_component_name])
+    /// Captures the screen and hashes it perceptually, ignoring the `exclusions`. Compare
+    /// hashes with [`PerceptualHash::distance`].
+    pub fn screen_hash(
+        &self, algorithm: HashAlgorithm, exclusions: &[Rect],
+    ) -> Result<PerceptualHash, AdbError> {
+        let dynamic_image = self.capture_screen_as_dynamic_image()?;
+        Ok(perceptual_hash(&dynamic_image, algorithm, exclusions))
     }
 
-    pub fn input_shown(&self) -> bool {
-        match self.adb_target(
-            &["shell", "dumpsys", "input_method", "|", "grep", "mInputShown"],
-        ) {
-            Some((output, _)) => {
-                output.contains("mInputShown=true")
-            }
-            None => false
-        }
+    pub fn start_activity(&self, activity_component_name: &str) -> Result<(), AdbError> {
+        check_activity_started(&self.adb_target(
+            &["shell", "am", "start", "-n", activity_component_name],
+        )?)
     }
 
-    pub fn input_text(&self, text: &str) -> Option<(String, String)> {
-        self.adb_target(&["shell", "input", "text", text])
+    pub fn input_shown(&self) -> Result<bool, AdbError> {
+        let output = self.adb_target(&["shell", "dumpsys", "input_method"])?;
+        Ok(output.stdout_string().contains("mInputShown=true"))
     }
 
-    pub fn input_key_event(&self, key_code: KeyCode) -> Option<(String, String)> {
+    pub fn input_text(&self, text: &str) -> Result<(), AdbError> {
+        self.adb_target_redacted(&["shell", "input", "text", text], &[3])
+            .map(|_| ())
+    }
+
+    pub fn input_key_event(&self, key_code: KeyCode) -> Result<(), AdbError> {
         self.adb_target(&["shell", "input", "keyevent", &*(key_code as u32).to_string()])
+            .map(|_| ())
     }
 
-    pub fn input_tap(&self, x: u32, y: u32) -> Option<(String, String)> {
+    pub fn input_tap(&self, x: u32, y: u32) -> Result<(), AdbError> {
         self.adb_target(&["shell", "input", "tap", &x.to_string(), &y.to_string()])
+            .map(|_| ())
     }
 
     pub fn input_swipe(
@@ -226,7 +511,7 @@ impl Adb {
         x_start: u32, x_end: u32,
         y_start: u32, y_end: u32,
         duration: u32,
-    ) -> Option<(String, String)> {
+    ) -> Result<(), AdbError> {
         self.adb_target(&[
             "shell", "input", "touchscreen", "swipe",
             &x_start.to_string(),
@@ -234,6 +519,149 @@ impl Adb {
             &x_end.to_string(),
             &y_end.to_string(),
             &duration.to_string()
-        ])
+        ]).map(|_| ())
+    }
+
+    pub fn stat(&self, remote: &str) -> Result<RemoteStat, AdbError> {
+        self.sync(|connection| connection.stat(remote))
+    }
+
+    pub fn list_dir(&self, remote: &str) -> Result<Vec<RemoteDirEntry>, AdbError> {
+        self.sync(|connection| connection.list(remote))
+    }
+
+    pub fn push(&self, local: &Path, remote: &str) -> Result<(), AdbError> {
+        self.push_with_progress(local, remote, |_| {})
+    }
+
+    /// Pushes a file or directory tree over the sync protocol, preserving mode and
+    /// modification time and reporting progress per file chunk. Empty directories are
+    /// created with `mkdir -p`, as the sync protocol cannot create them.
+    pub fn push_with_progress(
+        &self, local: &Path, remote: &str, mut progress: impl FnMut(&SyncProgress),
+    ) -> Result<(), AdbError> {
+        let empty_dirs = self.sync(|connection| connection.push(local, remote, &mut progress))?;
+        if empty_dirs.is_empty() {
+            return Ok(());
+        }
+        let quoted: Vec<String> = empty_dirs
+            .iter()
+            .map(|dir| format!("'{}'", dir.replace('\'', r"'\''")))
+            .collect();
+        let mut args = vec!["shell", "mkdir", "-p"];
+        args.extend(quoted.iter().map(String::as_str));
+        self.adb_target(&args).map(|_| ())
+    }
+
+    pub fn pull(&self, remote: &str, local: &Path) -> Result<(), AdbError> {
+        self.pull_with_progress(remote, local, |_| {})
     }
-}
\ No newline at end of file
+
+    /// Pulls a file or directory tree over the sync protocol, preserving modification time
+    /// and mode and reporting progress per file chunk.
+    pub fn pull_with_progress(
+        &self, remote: &str, local: &Path, mut progress: impl FnMut(&SyncProgress),
+    ) -> Result<(), AdbError> {
+        self.sync(|connection| connection.pull(remote, local, &mut progress))
+    }
+}
+
+pub(crate) fn resolve_adb_path(adb_path: Option<PathBuf>) -> Result<PathBuf, AdbError> {
+    let adb_path_buf = match adb_path {
+        Some(provided_adb_path) => provided_adb_path,
+        None => match which("adb") {
+            Ok(found_adb_path) => found_adb_path,
+            Err(_) => {
+                return Err(AdbError::AdbNotFound);
+            }
+        },
+    };
+    if !adb_path_buf.exists() {
+        return Err(AdbError::AdbNotFound);
+    }
+    Ok(adb_path_buf)
+}
+
+pub(crate) fn loggable_args(args: &[&str], sensitive: &[usize], redact: bool) -> String {
+    args.iter()
+        .enumerate()
+        .map(|(index, &arg)| {
+            if redact && sensitive.contains(&index) {
+                REDACTED_ARGUMENT
+            } else {
+                arg
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+pub(crate) fn run_error(err: io::Error, run_options: &RunOptions) -> AdbError {
+    match (err.kind(), run_options.timeout) {
+        (io::ErrorKind::TimedOut, Some(timeout)) => AdbError::Timeout(timeout),
+        (io::ErrorKind::Interrupted, _) if run_options.is_cancelled() => AdbError::Cancelled,
+        _ => AdbError::Spawn(err),
+    }
+}
+
+/// Errors for connections made to the adb server directly, such as sync transfers.
+pub(crate) fn server_error(err: io::Error, run_options: &RunOptions) -> AdbError {
+    if let Some(failure) = server_failure(&err) {
+        return device_state_error(&failure.0)
+            .unwrap_or_else(|| AdbError::ServerFailure(failure.0.clone()));
+    }
+    match (err.kind(), run_options.timeout) {
+        (io::ErrorKind::TimedOut, Some(timeout)) => AdbError::Timeout(timeout),
+        (io::ErrorKind::Interrupted, _) if run_options.is_cancelled() => AdbError::Cancelled,
+        _ => AdbError::Io(err),
+    }
+}
+
+fn device_state_error(message: &str) -> Option<AdbError> {
+    if message.contains("device offline") {
+        return Some(AdbError::DeviceOffline);
+    }
+    if message.contains("device unauthorized") {
+        return Some(AdbError::DeviceUnauthorized);
+    }
+    let message_body = message.trim_start_matches("error: ");
+    if message_body.starts_with("device") && message_body.contains("not found") {
+        return Some(AdbError::TargetNotOnline);
+    }
+    None
+}
+
+pub(crate) fn check_output(output: CommandOutput) -> Result<CommandOutput, AdbError> {
+    let stderr = output.stderr_string();
+    if let Some(err) = device_state_error(&stderr) {
+        return Err(err);
+    }
+    if !output.success() {
+        return Err(AdbError::NonZeroExit {
+            exit_code: output.exit_code,
+            stderr,
+        });
+    }
+    Ok(output)
+}
+
+pub(crate) fn has_exclusive_active_target(devices: &[Device]) -> bool {
+    devices.iter().filter(|device| device.is_online()).take(2).count() == 1
+}
+
+pub(crate) fn has_target(devices: &[Device], target: &str) -> bool {
+    devices.iter().any(|device| device.serial == target)
+}
+
+pub(crate) fn parse_display_size(stdout: String) -> Result<DisplaySize, AdbError> {
+    parse_wm_size(&stdout).ok_or(AdbError::UnparseableOutput(stdout))
+}
+
+pub(crate) fn check_activity_started(output: &CommandOutput) -> Result<(), AdbError> {
+    let stdout = output.stdout_string();
+    let stderr = output.stderr_string();
+    match stdout.lines().chain(stderr.lines()).find(|line| line.starts_with("Error:")) {
+        Some(error_line) => Err(AdbError::CommandFailed(error_line.to_string())),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,254 @@
+use std::collections::HashMap;
+
+use crate::adb::{Adb, DisplaySize};
+use crate::error::AdbError;
+
+/// The display's rotation away from its natural orientation, counter-clockwise as Android's
+/// `Surface.ROTATION_*` constants.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Rotation {
+    #[default]
+    Rotation0,
+    Rotation90,
+    Rotation180,
+    Rotation270,
+}
+
+impl Rotation {
+    /// Maps Android's rotation index, 0 to 3.
+    pub fn from_index(index: u32) -> Option<Self> {
+        match index {
+            0 => Some(Rotation::Rotation0),
+            1 => Some(Rotation::Rotation90),
+            2 => Some(Rotation::Rotation180),
+            3 => Some(Rotation::Rotation270),
+            _ => None,
+        }
+    }
+
+    pub fn index(&self) -> u32 {
+        match self {
+            Rotation::Rotation0 => 0,
+            Rotation::Rotation90 => 1,
+            Rotation::Rotation180 => 2,
+            Rotation::Rotation270 => 3,
+        }
+    }
+
+    /// Whether width and height are swapped relative to the natural orientation.
+    pub fn is_sideways(&self) -> bool {
+        self.index() % 2 == 1
+    }
+}
+
+/// Space taken from each edge, in pixels of the current rotation.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Insets {
+    pub left: u32,
+    pub top: u32,
+    pub right: u32,
+    pub bottom: u32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DisplayInfo {
+    pub display_id: u32,
+    pub name: Option<String>,
+    /// The panel resolution in its natural orientation.
+    pub physical_size: DisplaySize,
+    /// The resolution forced with `wm size`, if any.
+    pub override_size: Option<DisplaySize>,
+    pub physical_density: Option<u32>,
+    /// The density forced with `wm density`, if any.
+    pub override_density: Option<u32>,
+    pub rotation: Rotation,
+    pub refresh_rate: Option<f32>,
+    /// Display cutout insets such as a camera notch, `None` without a cutout.
+    pub cutout: Option<Insets>,
+}
+
+impl DisplayInfo {
+    /// The resolution screenshots and input use in the natural orientation, the override
+    /// size if one is set.
+    pub fn size(&self) -> DisplaySize {
+        self.override_size.unwrap_or(self.physical_size)
+    }
+
+    pub fn density(&self) -> Option<u32> {
+        self.override_density.or(self.physical_density)
+    }
+}
+
+impl Adb {
+    /// Describes every logical display from `dumpsys display`, combined with `wm size` and
+    /// `wm density` where the device supports querying them per display.
+    pub fn display_info(&self) -> Result<Vec<DisplayInfo>, AdbError> {
+        let dumpsys = self.adb_target(&["shell", "dumpsys", "display"])?.stdout_string();
+        let mut displays = parse_dumpsys_display(&dumpsys);
+        if displays.is_empty() {
+            return Err(AdbError::UnparseableOutput(dumpsys));
+        }
+        for display in &mut displays {
+            let display_id = display.display_id.to_string();
+            let wm = |command: &str| {
+                let args = match display.display_id {
+                    0 => vec!["shell", "wm", command],
+                    _ => vec!["shell", "wm", command, "-d", &display_id],
+                };
+                // `-d` needs Android 10, so dumpsys values stand in for older devices.
+                self.adb_target(&args).map(|output| output.stdout_string()).unwrap_or_default()
+            };
+            let (size, density) = (wm("size"), wm("density"));
+            let physical_size = labelled_value(&size, "Physical size:").and_then(parse_size);
+            if let Some(physical_size) = physical_size {
+                display.physical_size = physical_size;
+                display.override_size =
+                    labelled_value(&size, "Override size:").and_then(parse_size);
+            }
+            let physical_density =
+                labelled_value(&density, "Physical density:").and_then(parse_u32);
+            if physical_density.is_some() {
+                display.physical_density = physical_density;
+                display.override_density =
+                    labelled_value(&density, "Override density:").and_then(parse_u32);
+            }
+        }
+        Ok(displays)
+    }
+
+    /// Describes one display, see [`Adb::display_info`].
+    pub fn display_info_of(&self, display_id: u32) -> Result<DisplayInfo, AdbError> {
+        self.display_info()?
+            .into_iter()
+            .find(|display| display.display_id == display_id)
+            .ok_or_else(|| AdbError::CommandFailed(format!("no display with id {}", display_id)))
+    }
+}
+
+/// Parses the `DisplayInfo{...}` and `DisplayDeviceInfo{...}` lines of `dumpsys display`,
+/// preferring each logical display's override info over its base info.
+pub(crate) fn parse_dumpsys_display(output: &str) -> Vec<DisplayInfo> {
+    let mut device_sizes: HashMap<&str, (DisplaySize, Option<u32>)> = HashMap::new();
+    for line in output.lines().filter(|line| line.contains("DisplayDeviceInfo{")) {
+        let unique_id = between(line, "uniqueId=\"", "\"");
+        let size = between(line, "\", ", ",").and_then(parse_size);
+        if let (Some(unique_id), Some(size)) = (unique_id, size) {
+            device_sizes.insert(unique_id, (size, field(line, "density").and_then(parse_u32)));
+        }
+    }
+    let mut displays: Vec<(bool, DisplayInfo)> = Vec::new();
+    for line in output.lines() {
+        let is_override = line.contains("mOverrideDisplayInfo=DisplayInfo{");
+        if !is_override && !line.contains("mBaseDisplayInfo=DisplayInfo{") {
+            continue;
+        }
+        let Some(display) = parse_display_info(line, &device_sizes) else {
+            continue;
+        };
+        match displays.iter_mut().find(|(_, known)| known.display_id == display.display_id) {
+            Some(known) if is_override => *known = (true, display),
+            Some(_) => {}
+            None => displays.push((is_override, display)),
+        }
+    }
+    displays.into_iter().map(|(_, display)| display).collect()
+}
+
+fn parse_display_info(
+    line: &str, device_sizes: &HashMap<&str, (DisplaySize, Option<u32>)>,
+) -> Option<DisplayInfo> {
+    let display_id = field(line, "displayId").and_then(parse_u32)?;
+    let rotation = field(line, "rotation")
+        .and_then(parse_u32)
+        .and_then(Rotation::from_index)
+        .unwrap_or_default();
+    // `real` is the current logical size, already rotated and overridden.
+    let real = between(line, ", real ", ",").and_then(parse_size)?;
+    let natural = match rotation.is_sideways() {
+        true => DisplaySize { width: real.height, height: real.width },
+        false => real,
+    };
+    let density = field(line, "density").and_then(parse_u32);
+    let device = between(line, "uniqueId \"", "\"").and_then(|id| device_sizes.get(id));
+    let (physical_size, physical_density) = match device {
+        Some((size, device_density)) => (*size, device_density.or(density)),
+        None => (natural, density),
+    };
+    Some(DisplayInfo {
+        display_id,
+        name: between(line, "DisplayInfo{\"", "\"").map(str::to_string),
+        physical_size,
+        override_size: (natural != physical_size).then_some(natural),
+        physical_density,
+        override_density: density.filter(|density| Some(*density) != physical_density),
+        rotation,
+        refresh_rate: refresh_rate(line),
+        cutout: cutout(line),
+    })
+}
+
+fn refresh_rate(line: &str) -> Option<f32> {
+    if let Some(rate) = field(line, "renderFrameRate").and_then(|rate| rate.parse().ok()) {
+        return Some(rate);
+    }
+    let mode = field(line, "mode")?;
+    let supported_mode = between(line, &format!("{{id={},", mode), "}")?;
+    between(&format!("{},", supported_mode), "fps=", ",")?.parse().ok()
+}
+
+/// Reads `cutout DisplayCutout{insets=Rect(left, top - right, bottom) ...}`.
+fn cutout(line: &str) -> Option<Insets> {
+    let rect = between(line, "cutout DisplayCutout{insets=Rect(", ")")?;
+    let (left_top, right_bottom) = rect.split_once(" - ")?;
+    let (left, top) = left_top.split_once(", ")?;
+    let (right, bottom) = right_bottom.split_once(", ")?;
+    let insets = Insets {
+        left: parse_u32(left)?,
+        top: parse_u32(top)?,
+        right: parse_u32(right)?,
+        bottom: parse_u32(bottom)?,
+    };
+    (insets != Insets::default()).then_some(insets)
+}
+
+/// The first word after `, <name> ` or `{<name> `.
+fn field<'a>(line: &'a str, name: &str) -> Option<&'a str> {
+    [format!(", {} ", name), format!("{{{} ", name)]
+        .iter()
+        .find_map(|prefix| line.split_once(prefix.as_str()))
+        .and_then(|(_, rest)| rest.split([' ', ',', '}']).next())
+}
+
+fn between<'a>(text: &'a str, start: &str, end: &str) -> Option<&'a str> {
+    let (_, rest) = text.split_once(start)?;
+    rest.split_once(end).map(|(value, _)| value)
+}
+
+fn parse_u32(text: &str) -> Option<u32> {
+    text.trim().parse().ok()
+}
+
+/// Parses `1080 x 2400` as well as `1080x2400`.
+fn parse_size(text: &str) -> Option<DisplaySize> {
+    let (width, height) = text.split_once('x')?;
+    Some(DisplaySize {
+        width: parse_u32(width)?,
+        height: parse_u32(height)?,
+    })
+}
+
+/// The rest of the `wm size`/`wm density` line starting with `label`.
+fn labelled_value<'a>(output: &'a str, label: &str) -> Option<&'a str> {
+    output
+        .lines()
+        .find_map(|line| line.trim().strip_prefix(label))
+        .map(str::trim)
+}
+
+/// Parses `wm size`, preferring the override size since that is what screenshots and input
+/// use.
+pub(crate) fn parse_wm_size(output: &str) -> Option<DisplaySize> {
+    labelled_value(output, "Override size:")
+        .or_else(|| labelled_value(output, "Physical size:"))
+        .and_then(parse_size)
+}
@@ -0,0 +1,45 @@
+use std::time::Duration;
+
+use image::DynamicImage;
+
+use crate::adb::Adb;
+use crate::color::{average_color, pixel_at, Color, ColorSignature, ColorTolerance};
+use crate::error::AdbError;
+use crate::geometry::{Point, Rect};
+
+impl Adb {
+    pub fn pixel_at(&self, point: Point) -> Result<Color, AdbError> {
+        let dynamic_image = self.capture_screen_as_dynamic_image()?;
+        pixel_at(&dynamic_image, point)
+            .ok_or(AdbError::RegionOutOfBounds(Rect::new(point.x, point.y, 1, 1)))
+    }
+
+    /// The mean color within `rect`, clipped to the screen.
+    pub fn average_color(&self, rect: Rect) -> Result<Color, AdbError> {
+        let dynamic_image = self.capture_screen_as_dynamic_image()?;
+        average_color(&dynamic_image, rect).ok_or(AdbError::RegionOutOfBounds(rect))
+    }
+
+    pub fn matches_signature(&self, signature: &ColorSignature) -> Result<bool, AdbError> {
+        Ok(signature.matches(&self.capture_screen_as_dynamic_image()?))
+    }
+
+    /// Captures the screen until the pixel at `point` matches `color` within `tolerance`,
+    /// returning the matching frame.
+    pub fn wait_for_color(
+        &self, point: Point, color: Color, tolerance: ColorTolerance, timeout: Duration,
+    ) -> Result<DynamicImage, AdbError> {
+        self.wait_for_signature(&ColorSignature::new(vec![(point, color)], tolerance), timeout)
+    }
+
+    /// Captures the screen until every probe of `signature` matches, returning the matching
+    /// frame.
+    pub fn wait_for_signature(
+        &self, signature: &ColorSignature, timeout: Duration,
+    ) -> Result<DynamicImage, AdbError> {
+        self.poll_until(timeout, || {
+            let frame = self.capture_screen_as_dynamic_image()?;
+            Ok(signature.matches(&frame).then_some(frame))
+        })
+    }
+}
@@ -0,0 +1,81 @@
+use std::collections::HashMap;
+
+use crate::adb::Adb;
+use crate::error::AdbError;
+
+/// The commonly needed subset of device properties, see [`Adb::device_info`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct DeviceInfo {
+    pub sdk_level: Option<u32>,
+    pub release: Option<String>,
+    pub manufacturer: Option<String>,
+    pub model: Option<String>,
+    pub abi_list: Vec<String>,
+    pub fingerprint: Option<String>,
+    pub locale: Option<String>,
+}
+
+impl DeviceInfo {
+    pub fn from_properties(properties: &HashMap<String, String>) -> Self {
+        let property = |name: &str| {
+            properties.get(name).filter(|value| !value.is_empty()).cloned()
+        };
+        let abi_list = property("ro.product.cpu.abilist")
+            .or_else(|| property("ro.product.cpu.abi"))
+            .map(|abis| abis.split(',').map(str::to_string).collect())
+            .unwrap_or_default();
+        DeviceInfo {
+            sdk_level: property("ro.build.version.sdk").and_then(|sdk| sdk.parse().ok()),
+            release: property("ro.build.version.release"),
+            manufacturer: property("ro.product.manufacturer"),
+            model: property("ro.product.model"),
+            abi_list,
+            fingerprint: property("ro.build.fingerprint"),
+            locale: property("persist.sys.locale").or_else(|| property("ro.product.locale")),
+        }
+    }
+}
+
+impl Adb {
+    /// Returns every system property, fetched with `getprop` on first use and cached until
+    /// [`Adb::refresh_properties`] is called.
+    ///
+    /// Clones share the cache, including those returned by builders such as
+    /// [`Adb::with_timeout`]. [`Adb::with_server_addr`] may reach a different device, so its
+    /// copy starts with an empty cache.
+    pub fn properties(&self) -> Result<HashMap<String, String>, AdbError> {
+        if let Some(properties) = self.properties.lock().unwrap().as_ref() {
+            return Ok(properties.clone());
+        }
+        self.refresh_properties()
+    }
+
+    /// Fetches the system properties again, replacing the cached copy.
+    pub fn refresh_properties(&self) -> Result<HashMap<String, String>, AdbError> {
+        let output = self.adb_target(&["shell", "getprop"])?;
+        let properties = parse_properties(&output.stdout_string());
+        *self.properties.lock().unwrap() = Some(properties.clone());
+        Ok(properties)
+    }
+
+    pub fn property(&self, name: &str) -> Result<Option<String>, AdbError> {
+        Ok(self.properties()?.remove(name))
+    }
+
+    pub fn device_info(&self) -> Result<DeviceInfo, AdbError> {
+        Ok(DeviceInfo::from_properties(&self.properties()?))
+    }
+}
+
+/// Parses `getprop` output made of `[key]: [value]` lines.
+pub(crate) fn parse_properties(output: &str) -> HashMap<String, String> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let (key, value) = line.trim().split_once("]: [")?;
+            let key = key.strip_prefix('[')?;
+            let value = value.strip_suffix(']')?;
+            Some((key.to_string(), value.to_string()))
+        })
+        .collect()
+}
@@ -0,0 +1,75 @@
+use std::time::{Duration, Instant};
+
+use crate::adb::{has_exclusive_active_target, Adb};
+use crate::device::{DeviceState, RebootMode, WaitState};
+use crate::error::AdbError;
+
+/// How long a device rebooted into the bootloader may stay absent from adb before it counts
+/// as a fastboot-only bootloader, capped at half the remaining timeout.
+const BOOTLOADER_GRACE: Duration = Duration::from_secs(10);
+
+impl Adb {
+    /// Reboots the target into `mode` without waiting for it to come back.
+    pub fn reboot(&self, mode: RebootMode) -> Result<(), AdbError> {
+        match mode.argument() {
+            Some(argument) => self.adb_target(&["reboot", argument])?,
+            None => self.adb_target(&["reboot"])?,
+        };
+        // Cached properties describe the previous boot, which may even be a different build.
+        *self.properties.lock().unwrap() = None;
+        Ok(())
+    }
+
+    /// Reboots the target into `mode` and blocks until it is back in the matching state: fully
+    /// booted for [`RebootMode::System`], listed in recovery, sideload or the bootloader for
+    /// those modes, and gone from adb for fastbootd, which only fastboot can see. Most
+    /// bootloaders are not visible to adb either, so a device that does not show up in the
+    /// bootloader within [`BOOTLOADER_GRACE`] is taken to be in it.
+    pub fn reboot_and_wait(&self, mode: RebootMode, timeout: Duration) -> Result<(), AdbError> {
+        let started = Instant::now();
+        self.reboot(mode)?;
+        // The device stays listed for a moment after the reboot command returns, so wait for
+        // it to drop off first rather than mistaking the old session for the new one.
+        self.poll_until(timeout, || {
+            let devices = self.list_devices()?;
+            let online = match &self.target_serial {
+                Some(serial) => devices
+                    .iter()
+                    .any(|device| &device.serial == serial && device.is_online()),
+                None => has_exclusive_active_target(&devices),
+            };
+            Ok((!online).then_some(()))
+        })?;
+        let remaining = timeout.saturating_sub(started.elapsed());
+        match mode.wait_state() {
+            Some(WaitState::Device) => self.wait_for_boot_completed(remaining),
+            Some(WaitState::Bootloader) => self.wait_for_bootloader(remaining),
+            Some(state) => self.wait_for_state(state, remaining),
+            None => self.wait_for_state(WaitState::Disconnect, remaining),
+        }
+    }
+
+    /// Waits for the target to be listed in the bootloader, or to stay absent from adb for
+    /// the grace period as fastboot-only bootloaders do.
+    fn wait_for_bootloader(&self, timeout: Duration) -> Result<(), AdbError> {
+        let grace = BOOTLOADER_GRACE.min(timeout / 2);
+        let mut absent_since = Instant::now();
+        self.poll_until(timeout, || {
+            let devices = self.list_devices()?;
+            let states: Vec<&DeviceState> = devices
+                .iter()
+                .filter(|device| {
+                    self.target_serial.as_ref().is_none_or(|serial| &device.serial == serial)
+                })
+                .map(|device| &device.state)
+                .collect();
+            if states.contains(&&DeviceState::Bootloader) {
+                return Ok(Some(()));
+            }
+            if !states.is_empty() {
+                absent_since = Instant::now();
+            }
+            Ok((absent_since.elapsed() >= grace).then_some(()))
+        })
+    }
+}
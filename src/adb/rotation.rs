@@ -0,0 +1,132 @@
+use image::DynamicImage;
+
+use crate::adb::display::{parse_dumpsys_display, Rotation};
+use crate::adb::{Adb, DisplaySize};
+use crate::error::AdbError;
+use crate::geometry::Point;
+
+/// Which orientation a coordinate is expressed in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum CoordinateSpace {
+    /// The panel's natural orientation, regardless of the current rotation.
+    Natural,
+    /// The display as currently rotated, i.e. as the user sees it.
+    #[default]
+    Rotated,
+}
+
+/// Maps points between screenshots and `input` for one display rotation.
+///
+/// Most devices both capture and take input in [`CoordinateSpace::Rotated`], in which case
+/// the mapping is the identity, but some capture the framebuffer in its natural orientation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ScreenTransform {
+    pub rotation: Rotation,
+    /// Display size in the natural orientation.
+    pub natural_size: DisplaySize,
+    pub screenshot_space: CoordinateSpace,
+    pub input_space: CoordinateSpace,
+}
+
+impl ScreenTransform {
+    pub fn new(rotation: Rotation, natural_size: DisplaySize) -> Self {
+        ScreenTransform {
+            rotation,
+            natural_size,
+            screenshot_space: CoordinateSpace::Rotated,
+            input_space: CoordinateSpace::Rotated,
+        }
+    }
+
+    /// Display size as currently rotated.
+    pub fn rotated_size(&self) -> DisplaySize {
+        let DisplaySize { width, height } = self.natural_size;
+        match self.rotation.is_sideways() {
+            true => DisplaySize { width: height, height: width },
+            false => self.natural_size,
+        }
+    }
+
+    /// Maps a point in the natural orientation to the rotated display.
+    pub fn natural_to_rotated(&self, point: Point) -> Point {
+        let DisplaySize { width, height } = self.natural_size;
+        let (x, y) = (point.x.min(width - 1), point.y.min(height - 1));
+        match self.rotation {
+            Rotation::Rotation0 => Point::new(x, y),
+            Rotation::Rotation90 => Point::new(y, width - 1 - x),
+            Rotation::Rotation180 => Point::new(width - 1 - x, height - 1 - y),
+            Rotation::Rotation270 => Point::new(height - 1 - y, x),
+        }
+    }
+
+    /// Maps a point on the rotated display back to the natural orientation.
+    pub fn rotated_to_natural(&self, point: Point) -> Point {
+        let DisplaySize { width, height } = self.rotated_size();
+        let (x, y) = (point.x.min(width - 1), point.y.min(height - 1));
+        match self.rotation {
+            Rotation::Rotation0 => Point::new(x, y),
+            Rotation::Rotation90 => Point::new(height - 1 - y, x),
+            Rotation::Rotation180 => Point::new(width - 1 - x, height - 1 - y),
+            Rotation::Rotation270 => Point::new(y, width - 1 - x),
+        }
+    }
+
+    /// Maps a point found on a screenshot, e.g. by OCR, to where `input tap` must touch.
+    pub fn screenshot_to_input(&self, point: Point) -> Point {
+        self.convert(point, self.screenshot_space, self.input_space)
+    }
+
+    pub fn input_to_screenshot(&self, point: Point) -> Point {
+        self.convert(point, self.input_space, self.screenshot_space)
+    }
+
+    fn convert(&self, point: Point, from: CoordinateSpace, to: CoordinateSpace) -> Point {
+        match (from, to) {
+            (CoordinateSpace::Natural, CoordinateSpace::Rotated) => self.natural_to_rotated(point),
+            (CoordinateSpace::Rotated, CoordinateSpace::Natural) => self.rotated_to_natural(point),
+            _ => point,
+        }
+    }
+}
+
+impl Adb {
+    /// The current rotation of the default display.
+    pub fn rotation(&self) -> Result<Rotation, AdbError> {
+        let output = self.adb_target(&["shell", "dumpsys", "display"])?.stdout_string();
+        parse_dumpsys_display(&output)
+            .into_iter()
+            .find(|display| display.display_id == 0)
+            .map(|display| display.rotation)
+            .ok_or(AdbError::UnparseableOutput(output))
+    }
+
+    /// Turns sensor-driven rotation on or off, leaving the display in its current rotation
+    /// when turned off.
+    pub fn set_auto_rotation(&self, enabled: bool) -> Result<(), AdbError> {
+        let enabled = if enabled { "1" } else { "0" };
+        self.adb_target(&["shell", "settings", "put", "system", "accelerometer_rotation", enabled])
+            .map(|_| ())
+    }
+
+    /// Locks the display in `rotation`, turning off auto-rotation.
+    pub fn set_rotation(&self, rotation: Rotation) -> Result<(), AdbError> {
+        self.set_auto_rotation(false)?;
+        let index = rotation.index().to_string();
+        self.adb_target(&["shell", "settings", "put", "system", "user_rotation", &index])
+            .map(|_| ())
+    }
+
+    /// Builds the transform for the default display's current rotation, telling from the
+    /// dimensions of `screenshot` whether this device captures in the natural orientation.
+    pub fn screen_transform(&self, screenshot: &DynamicImage) -> Result<ScreenTransform, AdbError> {
+        let display = self.display_info_of(0)?;
+        let mut transform = ScreenTransform::new(display.rotation, display.size());
+        let natural = transform.natural_size;
+        if display.rotation.is_sideways()
+            && (screenshot.width(), screenshot.height()) == (natural.width, natural.height)
+        {
+            transform.screenshot_space = CoordinateSpace::Natural;
+        }
+        Ok(transform)
+    }
+}
@@ -0,0 +1,221 @@
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use tracing::warn;
+
+use crate::adb::{Adb, WAIT_POLL_INTERVAL};
+use crate::error::AdbError;
+use crate::runner::CancellationToken;
+
+/// The longest recording `screenrecord` makes in one go.
+const SEGMENT_LIMIT: Duration = Duration::from_secs(180);
+/// A segment ending this much before its limit was stopped or failed rather than expiring.
+const SEGMENT_SLACK: Duration = Duration::from_secs(5);
+/// How long [`ScreenRecording::stop`] waits for `screenrecord` to finalize its file before
+/// aborting the segment outright.
+const STOP_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Debug, Default)]
+pub struct ScreenRecordOptions {
+    /// Video bit rate in bits per second, `screenrecord` defaults to 20Mbps.
+    pub bit_rate: Option<u32>,
+    /// Video width and height, defaulting to the display resolution.
+    pub size: Option<(u32, u32)>,
+    /// Total recording length, split into 3 minute segments. Unlimited when `None`.
+    pub time_limit: Option<Duration>,
+    /// Display to record on multi-display devices, Android 10+.
+    pub display_id: Option<u64>,
+    /// Device directory segments are recorded into, `/data/local/tmp` when `None`.
+    pub remote_dir: Option<String>,
+}
+
+/// A `screenrecord` session running on the device, see [`Adb::start_screen_recording`].
+///
+/// `screenrecord` stops after 3 minutes, so longer recordings are chained into consecutive
+/// MP4 segments. Dropping the recording stops it but leaves the segments on the device.
+pub struct ScreenRecording {
+    adb: Adb,
+    name_prefix: String,
+    segments: Arc<Mutex<Vec<String>>>,
+    stopping: CancellationToken,
+    abort: CancellationToken,
+    handle: Option<JoinHandle<Result<(), AdbError>>>,
+}
+
+/// What [`ScreenRecording::stop`] brought back from the device.
+#[derive(Debug)]
+pub struct StoppedRecording {
+    /// Local paths of the pulled segments, in recording order.
+    pub segments: Vec<PathBuf>,
+    /// Why `screenrecord` failed, if it did. Segments recorded up to the failure are still
+    /// pulled, since that is usually when the video matters most.
+    pub recording_error: Option<AdbError>,
+    /// Device paths of segments that could not be pulled, with the reason. These are left on
+    /// the device.
+    pub unpulled: Vec<(String, AdbError)>,
+}
+
+impl StoppedRecording {
+    /// Whether the recording ran without error and every segment was pulled.
+    pub fn is_complete(&self) -> bool {
+        self.recording_error.is_none() && self.unpulled.is_empty()
+    }
+}
+
+impl Adb {
+    /// Starts recording the screen in the background until [`ScreenRecording::stop`] is
+    /// called or the options' time limit expires.
+    pub fn start_screen_recording(
+        &self, options: ScreenRecordOptions,
+    ) -> Result<ScreenRecording, AdbError> {
+        let started_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        let remote_dir = options.remote_dir.as_deref().unwrap_or("/data/local/tmp");
+        let name_prefix = format!("{}/dark_instruments-{}-", remote_dir, started_at);
+        let segments = Arc::new(Mutex::new(Vec::new()));
+        let stopping = CancellationToken::new();
+        let abort = CancellationToken::new();
+        let adb = self.without_timeout().with_cancellation(abort.clone());
+        let handle = {
+            let name_prefix = name_prefix.clone();
+            let segments = segments.clone();
+            let stopping = stopping.clone();
+            thread::Builder::new()
+                .name("adb-screenrecord".to_string())
+                .spawn(move || adb.record_segments(&options, &name_prefix, &segments, &stopping))
+                .map_err(AdbError::Io)?
+        };
+        Ok(ScreenRecording {
+            adb: self.clone(),
+            name_prefix,
+            segments,
+            stopping,
+            abort,
+            handle: Some(handle),
+        })
+    }
+
+    fn record_segments(
+        &self,
+        options: &ScreenRecordOptions,
+        name_prefix: &str,
+        segments: &Mutex<Vec<String>>,
+        stopping: &CancellationToken,
+    ) -> Result<(), AdbError> {
+        let started = Instant::now();
+        for index in 0.. {
+            // Whole seconds, as that is what `--time-limit` takes.
+            let remaining = options.time_limit.map(|total| {
+                Duration::from_secs(total.as_secs().saturating_sub(started.elapsed().as_secs()))
+            });
+            let limit = remaining.map_or(SEGMENT_LIMIT, |remaining| remaining.min(SEGMENT_LIMIT));
+            if stopping.is_cancelled() || limit.as_secs() == 0 {
+                return Ok(());
+            }
+            let remote = format!("{}{:03}.mp4", name_prefix, index);
+            segments.lock().unwrap().push(remote.clone());
+            let mut args = vec!["shell".to_string(), "screenrecord".to_string()];
+            if let Some(bit_rate) = options.bit_rate {
+                args.extend(["--bit-rate".to_string(), bit_rate.to_string()]);
+            }
+            if let Some((width, height)) = options.size {
+                args.extend(["--size".to_string(), format!("{}x{}", width, height)]);
+            }
+            if let Some(display_id) = options.display_id {
+                args.extend(["--display-id".to_string(), display_id.to_string()]);
+            }
+            args.extend(["--time-limit".to_string(), limit.as_secs().to_string(), remote]);
+            let segment_started = Instant::now();
+            match self.adb_target(&args.iter().map(String::as_str).collect::<Vec<_>>()) {
+                Err(_) if stopping.is_cancelled() => return Ok(()),
+                result => result?,
+            };
+            if segment_started.elapsed() + SEGMENT_SLACK < limit {
+                return Ok(());
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ScreenRecording {
+    /// Device paths of the segments recorded so far, including the one in progress.
+    pub fn remote_segments(&self) -> Vec<String> {
+        self.segments.lock().unwrap().clone()
+    }
+
+    /// Stops recording, pulls every segment that exists into `local_dir` and deletes the
+    /// pulled ones from the device. Errors are reported in the result rather than returned
+    /// early, so a failed recording still yields whatever video was written.
+    pub fn stop(mut self, local_dir: &Path) -> StoppedRecording {
+        let recording_error = self.finish().err();
+        let mut stopped = StoppedRecording {
+            segments: Vec::new(),
+            recording_error,
+            unpulled: Vec::new(),
+        };
+        let mut pulled_remotes = Vec::new();
+        for remote in self.remote_segments() {
+            let name = remote.rsplit('/').next().unwrap_or(&remote);
+            let local = local_dir.join(name);
+            // A segment is registered before `screenrecord` runs, which may fail to write it.
+            let result = match self.adb.stat(&remote) {
+                Ok(stat) if !stat.exists() => continue,
+                Ok(_) => self.adb.pull(&remote, &local),
+                Err(err) => Err(err),
+            };
+            match result {
+                Ok(()) => {
+                    stopped.segments.push(local);
+                    pulled_remotes.push(remote);
+                }
+                Err(err) => stopped.unpulled.push((remote, err)),
+            }
+        }
+        if !pulled_remotes.is_empty() {
+            let mut args = vec!["shell", "rm", "-f"];
+            args.extend(pulled_remotes.iter().map(String::as_str));
+            if let Err(err) = self.adb.adb_target(&args) {
+                warn!(error = %err, "could not delete pulled screen recording segments");
+            }
+        }
+        stopped
+    }
+
+    /// Interrupts `screenrecord` so it finalizes the current segment, then waits for the
+    /// recording thread, aborting the segment if it does not end within [`STOP_TIMEOUT`].
+    fn finish(&mut self) -> Result<(), AdbError> {
+        let Some(handle) = self.handle.take() else {
+            return Ok(());
+        };
+        self.stopping.cancel();
+        let deadline = Instant::now() + STOP_TIMEOUT;
+        while !handle.is_finished() {
+            if Instant::now() >= deadline {
+                self.abort.cancel();
+                break;
+            }
+            // Repeated, since a new segment may start right after an interrupt is sent. Quoted
+            // for the device shell and anchored on the binary so neither the shell running
+            // `screenrecord` nor this `pkill` match.
+            let pattern = format!("'^screenrecord .*{}'", self.name_prefix);
+            let _ = self.adb.exec_unchecked(
+                &self.adb.target_args(&["shell", "pkill", "-INT", "-f", &pattern]),
+                &[],
+            );
+            thread::sleep(WAIT_POLL_INTERVAL);
+        }
+        handle.join().unwrap_or(Ok(()))
+    }
+}
+
+impl Drop for ScreenRecording {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
@@ -0,0 +1,101 @@
+use std::time::{Duration, Instant};
+
+use image::{DynamicImage, RgbaImage};
+
+use crate::adb::Adb;
+use crate::error::AdbError;
+use crate::geometry::Rect;
+
+/// Fraction of compared pixels that must match for two frames to count as the same screen by
+/// default. The remaining 0.1% absorbs a blinking cursor, while a toggle or checkbox on a
+/// phone screen still counts as a change.
+pub(crate) const DEFAULT_SIMILARITY_THRESHOLD: f64 = 0.999;
+/// Largest per-channel difference between two captures of a pixel that counts as capture
+/// noise rather than a change, e.g. from dithering or hardware composition.
+const PIXEL_TOLERANCE: u8 = 8;
+
+impl Adb {
+    /// Returns a copy of this `Adb` that treats two frames as the same screen in
+    /// [`Adb::wait_until_stable`] and [`Adb::wait_for_change`] when at least `threshold` of
+    /// their compared pixels match, from 0.0 to 1.0.
+    pub fn with_similarity_threshold(&self, threshold: f64) -> Adb {
+        let mut adb = self.clone();
+        adb.similarity_threshold = threshold;
+        adb
+    }
+
+    /// Captures the screen repeatedly until it has not changed for `min_stable_duration`,
+    /// e.g. once an animation finished, returning the last frame.
+    pub fn wait_until_stable(
+        &self, min_stable_duration: Duration, timeout: Duration,
+    ) -> Result<DynamicImage, AdbError> {
+        self.wait_until_stable_excluding(min_stable_duration, &[], timeout)
+    }
+
+    /// Like [`Adb::wait_until_stable`], ignoring changes inside `exclusions` such as the
+    /// status bar clock.
+    pub fn wait_until_stable_excluding(
+        &self, min_stable_duration: Duration, exclusions: &[Rect], timeout: Duration,
+    ) -> Result<DynamicImage, AdbError> {
+        let mut stable_since: Option<(Instant, RgbaImage)> = None;
+        self.poll_until(timeout, || {
+            let frame = self.capture_screen_as_dynamic_image()?;
+            let pixels = frame.to_rgba8();
+            match &stable_since {
+                // Compared against the first frame of the run so slow drifts are noticed.
+                Some((since, reference)) if self.same_screen(reference, &pixels, exclusions) => {
+                    if since.elapsed() >= min_stable_duration {
+                        return Ok(Some(frame));
+                    }
+                }
+                _ => stable_since = Some((Instant::now(), pixels)),
+            }
+            Ok(None)
+        })
+    }
+
+    /// Captures the screen repeatedly until it differs from `since_frame`, returning the
+    /// first differing frame.
+    pub fn wait_for_change(
+        &self, since_frame: &DynamicImage, timeout: Duration,
+    ) -> Result<DynamicImage, AdbError> {
+        self.wait_for_change_excluding(since_frame, &[], timeout)
+    }
+
+    /// Like [`Adb::wait_for_change`], ignoring changes inside `exclusions`.
+    pub fn wait_for_change_excluding(
+        &self, since_frame: &DynamicImage, exclusions: &[Rect], timeout: Duration,
+    ) -> Result<DynamicImage, AdbError> {
+        let reference = since_frame.to_rgba8();
+        self.poll_until(timeout, || {
+            let frame = self.capture_screen_as_dynamic_image()?;
+            let changed = !self.same_screen(&reference, &frame.to_rgba8(), exclusions);
+            Ok(changed.then_some(frame))
+        })
+    }
+
+    fn same_screen(&self, reference: &RgbaImage, frame: &RgbaImage, exclusions: &[Rect]) -> bool {
+        similarity(reference, frame, exclusions) >= self.similarity_threshold
+    }
+}
+
+/// The fraction of pixels outside `exclusions` whose channels all lie within
+/// [`PIXEL_TOLERANCE`], 0.0 for frames of different sizes.
+fn similarity(reference: &RgbaImage, frame: &RgbaImage, exclusions: &[Rect]) -> f64 {
+    if reference.dimensions() != frame.dimensions() {
+        return 0.0;
+    }
+    let (mut matching, mut compared) = (0u64, 0u64);
+    for ((x, y, before), after) in reference.enumerate_pixels().zip(frame.pixels()) {
+        if exclusions.iter().any(|exclusion| exclusion.contains(x, y)) {
+            continue;
+        }
+        compared += 1;
+        let within = before.0.iter().zip(after.0).all(|(a, b)| a.abs_diff(b) <= PIXEL_TOLERANCE);
+        matching += within as u64;
+    }
+    match compared {
+        0 => 1.0,
+        compared => matching as f64 / compared as f64,
+    }
+}
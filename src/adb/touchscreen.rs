@@ -0,0 +1,299 @@
+use std::iter;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::adb::rotation::ScreenTransform;
+use crate::adb::{Adb, DisplaySize};
+use crate::error::AdbError;
+use crate::geometry::Point;
+use crate::gesture::Gesture;
+
+/// `input motionevent` arrived in Android 11.
+const MOTION_EVENT_SDK_LEVEL: u32 = 30;
+/// Sampling interval for `sendevent`, about 30 Hz. Every event is a separate `sendevent`
+/// process, so sampling finer mostly adds process start-up time to the gesture.
+const SENDEVENT_STEP: Duration = Duration::from_millis(33);
+/// Sampling interval for `input motionevent`, whose every call starts a new VM.
+const MOTION_EVENT_STEP: Duration = Duration::from_millis(100);
+/// Device directory gesture scripts are pushed to.
+const SCRIPT_DIR: &str = "/data/local/tmp";
+
+const EV_SYN: u16 = 0x00;
+const EV_KEY: u16 = 0x01;
+const EV_ABS: u16 = 0x03;
+const SYN_REPORT: u16 = 0x00;
+const BTN_TOUCH: u16 = 0x14a;
+const ABS_MT_SLOT: u16 = 0x2f;
+const ABS_MT_POSITION_X: u16 = 0x35;
+const ABS_MT_POSITION_Y: u16 = 0x36;
+const ABS_MT_TRACKING_ID: u16 = 0x39;
+const ABS_MT_PRESSURE: u16 = 0x3a;
+
+/// A multi-touch input device as reported by `getevent -p`, in raw device units.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TouchscreenDevice {
+    /// The device node, e.g. `/dev/input/event2`.
+    pub path: String,
+    pub name: Option<String>,
+    pub x_range: (i32, i32),
+    pub y_range: (i32, i32),
+    /// The highest multi-touch slot, `None` for devices without slots.
+    pub max_slot: Option<i32>,
+    pub pressure_range: Option<(i32, i32)>,
+    /// Whether the device reports `BTN_TOUCH` along with contacts.
+    pub has_btn_touch: bool,
+}
+
+impl Adb {
+    /// Finds the touchscreen among the devices listed by `getevent -p`, preferring direct
+    /// input devices over e.g. touchpads.
+    pub fn touchscreen(&self) -> Result<TouchscreenDevice, AdbError> {
+        let output = self.adb_target(&["shell", "getevent", "-p"])?.stdout_string();
+        let mut devices = parse_getevent(&output);
+        let index = devices.iter().position(|(direct, _)| *direct).unwrap_or_default();
+        match devices.is_empty() {
+            true => Err(AdbError::CommandFailed("no touchscreen in getevent -p".to_string())),
+            false => Ok(devices.swap_remove(index).1),
+        }
+    }
+
+    /// Performs `gesture` on the default display, with points in screenshot coordinates.
+    ///
+    /// Single-finger gestures use `input motionevent` where available, anything else writes
+    /// raw events with `sendevent` to the [`Adb::touchscreen`]. Either way the gesture runs
+    /// as a script pushed to the device, one process per event, so it takes longer than
+    /// planned by the time those processes need to start, typically a few milliseconds each.
+    pub fn perform_gesture(&self, gesture: &Gesture) -> Result<(), AdbError> {
+        if gesture.pointers().is_empty() {
+            return Ok(());
+        }
+        let sdk_level = self.device_info()?.sdk_level;
+        let script = match gesture.pointers().len() == 1
+            && sdk_level.is_some_and(|level| level >= MOTION_EVENT_SDK_LEVEL)
+        {
+            true => motion_event_script(gesture),
+            false => {
+                let touchscreen = self.touchscreen()?;
+                let display = self.display_info_of(0)?;
+                let transform = ScreenTransform::new(display.rotation, display.size());
+                sendevent_script(gesture, &touchscreen, &transform)?
+            }
+        };
+        self.run_script(&script)
+    }
+
+    /// Pushes `script` to the device and runs it there, since a gesture's script easily
+    /// exceeds what fits in one `adb shell` command, 4KB before Android 7.
+    fn run_script(&self, script: &str) -> Result<(), AdbError> {
+        let started_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        let remote = format!("{}/dark_instruments-gesture-{}.sh", SCRIPT_DIR, started_at);
+        let mtime = (started_at / 1000) as u32;
+        self.sync(|connection| {
+            connection.send(&remote, 0o100644, mtime, &mut script.as_bytes(), &mut |_| {})
+        })?;
+        let result = self.adb_target(&["shell", "sh", &remote]);
+        let cleanup = self.adb_target(&["shell", "rm", "-f", &remote]);
+        result.and(cleanup).map(|_| ())
+    }
+}
+
+/// Parses `getevent -p` into every device reporting multi-touch positions, each flagged
+/// with whether it has `INPUT_PROP_DIRECT`.
+pub(crate) fn parse_getevent(output: &str) -> Vec<(bool, TouchscreenDevice)> {
+    let mut devices: Vec<(bool, TouchscreenDevice)> = Vec::new();
+    let mut section = "";
+    for line in output.lines() {
+        let trimmed = line.trim();
+        let added = trimmed.strip_prefix("add device ").and_then(|rest| rest.split_once(": "));
+        if let Some((_, path)) = added {
+            let device = TouchscreenDevice { path: path.trim().to_string(), ..Default::default() };
+            devices.push((false, device));
+            section = "";
+            continue;
+        }
+        let Some((direct, device)) = devices.last_mut() else {
+            continue;
+        };
+        if let Some(name) = trimmed.strip_prefix("name:") {
+            device.name = Some(name.trim().trim_matches('"').to_string());
+            continue;
+        }
+        if trimmed == "input props:" {
+            section = "props";
+            continue;
+        }
+        let content = match trimmed.split_once("):") {
+            Some((header, content)) => {
+                section = header.split(" (").next().unwrap_or_default();
+                content.trim()
+            }
+            None => trimmed,
+        };
+        match section {
+            "KEY" => {
+                let btn_touch = format!("{:04x}", BTN_TOUCH);
+                device.has_btn_touch |= content.split_whitespace().any(|code| code == btn_touch);
+            }
+            "ABS" => {
+                let Some((code, range)) = parse_abs_axis(content) else {
+                    continue;
+                };
+                match code {
+                    ABS_MT_SLOT => device.max_slot = Some(range.1),
+                    ABS_MT_POSITION_X => device.x_range = range,
+                    ABS_MT_POSITION_Y => device.y_range = range,
+                    ABS_MT_PRESSURE => device.pressure_range = Some(range),
+                    _ => {}
+                }
+            }
+            "props" => *direct |= content == "INPUT_PROP_DIRECT",
+            _ => {}
+        }
+    }
+    // Both multi-touch position axes must be present and span something.
+    devices.retain(|(_, device)| {
+        device.x_range.1 > device.x_range.0 && device.y_range.1 > device.y_range.0
+    });
+    devices
+}
+
+/// Parses `0035  : value 0, min 0, max 1079, fuzz 0, flat 0, resolution 0`.
+fn parse_abs_axis(content: &str) -> Option<(u16, (i32, i32))> {
+    let (code, details) = content.split_once(':')?;
+    let code = u16::from_str_radix(code.trim(), 16).ok()?;
+    let value = |name: &str| {
+        details
+            .split(',')
+            .find_map(|field| field.trim().strip_prefix(name))
+            .and_then(|value| value.trim().parse().ok())
+    };
+    Some((code, (value("min")?, value("max")?)))
+}
+
+fn motion_event_script(gesture: &Gesture) -> String {
+    let mut commands = Vec::new();
+    let mut previous: Option<Point> = None;
+    let mut last_time = Duration::ZERO;
+    for (time, positions) in gesture.frames(MOTION_EVENT_STEP) {
+        let (action, point) = match (previous, positions[0]) {
+            (None, Some(point)) => ("DOWN", point),
+            (Some(before), Some(point)) if before != point => ("MOVE", point),
+            _ => continue,
+        };
+        if time > last_time {
+            commands.push(sleep(time - last_time));
+            last_time = time;
+        }
+        commands.push(format!("input motionevent {} {} {}", action, point.x, point.y));
+        previous = Some(point);
+    }
+    if let Some(point) = previous {
+        commands.push(format!("input motionevent UP {} {}", point.x, point.y));
+    }
+    commands.join("\n")
+}
+
+/// Writes the gesture as multi-touch protocol B events: each finger gets a free slot and a
+/// tracking id while down, and every frame ends with `SYN_REPORT`.
+fn sendevent_script(
+    gesture: &Gesture, touchscreen: &TouchscreenDevice, transform: &ScreenTransform,
+) -> Result<String, AdbError> {
+    let unsupported = |reason: String| {
+        AdbError::CommandFailed(format!("{} on {}", reason, touchscreen.path))
+    };
+    let Some(max_slot) = touchscreen.max_slot else {
+        return Err(unsupported("no multi-touch slots".to_string()));
+    };
+    let event = |kind: u16, code: u16, value: i32| {
+        format!("sendevent {} {} {} {}", touchscreen.path, kind, code, value)
+    };
+    let pressure = touchscreen.pressure_range.map(|(min, max)| (min + max + 1) / 2);
+    let pointer_count = gesture.pointers().len();
+    let mut slots: Vec<Option<usize>> = vec![None; max_slot.max(0) as usize + 1];
+    // The kernel remembers the selected slot and each slot's axes, so only changes are sent.
+    let mut current_slot: Option<usize> = None;
+    let mut reported: Vec<Option<(i32, i32)>> = vec![None; pointer_count];
+    let mut was_touching = false;
+    let mut last_time = Duration::ZERO;
+    let mut commands = Vec::new();
+    let lift_all = (gesture.duration(), vec![None; pointer_count]);
+    let frames = gesture.frames(SENDEVENT_STEP).into_iter().chain(iter::once(lift_all));
+    for (time, positions) in frames {
+        let mut events = Vec::new();
+        for (pointer, now) in positions.iter().enumerate() {
+            let now = now.map(|point| to_device(point, touchscreen, transform));
+            let before = reported[pointer];
+            let slot = match (before, now) {
+                (None, None) => continue,
+                (Some(before), Some(now)) if before == now => continue,
+                (None, Some(_)) => {
+                    let free = slots.iter().position(Option::is_none).ok_or_else(|| {
+                        unsupported(format!("more than {} simultaneous touches", slots.len()))
+                    })?;
+                    slots[free] = Some(pointer);
+                    free
+                }
+                _ => slots.iter().position(|taken| *taken == Some(pointer)).unwrap(),
+            };
+            if current_slot != Some(slot) {
+                events.push(event(EV_ABS, ABS_MT_SLOT, slot as i32));
+                current_slot = Some(slot);
+            }
+            reported[pointer] = now;
+            let Some((x, y)) = now else {
+                events.push(event(EV_ABS, ABS_MT_TRACKING_ID, -1));
+                slots[slot] = None;
+                continue;
+            };
+            if before.is_none() {
+                events.push(event(EV_ABS, ABS_MT_TRACKING_ID, pointer as i32));
+            }
+            if before.map(|(before_x, _)| before_x) != Some(x) {
+                events.push(event(EV_ABS, ABS_MT_POSITION_X, x));
+            }
+            if before.map(|(_, before_y)| before_y) != Some(y) {
+                events.push(event(EV_ABS, ABS_MT_POSITION_Y, y));
+            }
+            if let (None, Some(pressure)) = (before, pressure) {
+                events.push(event(EV_ABS, ABS_MT_PRESSURE, pressure));
+            }
+        }
+        if events.is_empty() {
+            continue;
+        }
+        let is_touching = reported.iter().any(Option::is_some);
+        if touchscreen.has_btn_touch && was_touching != is_touching {
+            events.push(event(EV_KEY, BTN_TOUCH, is_touching as i32));
+        }
+        was_touching = is_touching;
+        events.push(event(EV_SYN, SYN_REPORT, 0));
+        if time > last_time {
+            commands.push(sleep(time - last_time));
+            last_time = time;
+        }
+        commands.extend(events);
+    }
+    Ok(commands.join("\n"))
+}
+
+/// Maps a point on the rotated display to raw touchscreen units, which follow the panel's
+/// natural orientation.
+fn to_device(
+    point: Point, touchscreen: &TouchscreenDevice, transform: &ScreenTransform,
+) -> (i32, i32) {
+    let natural = transform.rotated_to_natural(point);
+    let DisplaySize { width, height } = transform.natural_size;
+    let scale = |value: u32, size: u32, (min, max): (i32, i32)| {
+        min + (value as i64 * (max - min) as i64 / size.saturating_sub(1).max(1) as i64) as i32
+    };
+    (
+        scale(natural.x, width, touchscreen.x_range),
+        scale(natural.y, height, touchscreen.y_range),
+    )
+}
+
+fn sleep(duration: Duration) -> String {
+    format!("sleep {:.3}", duration.as_secs_f64())
+}
@@ -0,0 +1,59 @@
+use crate::adb::{device_state_error, Adb};
+use crate::error::AdbError;
+use crate::runner::CommandOutput;
+
+const DEFAULT_TCPIP_PORT: u16 = 5555;
+
+impl Adb {
+    /// Connects to a device over Wi-Fi, returning the `host:port` serial it is listed under.
+    ///
+    /// `port` defaults to 5555, the port `adb tcpip` switches to by default.
+    pub fn connect(&self, host: &str, port: Option<u16>) -> Result<String, AdbError> {
+        let serial = format!("{}:{}", host, port.unwrap_or(DEFAULT_TCPIP_PORT));
+        let output = self.exec_unchecked(&["connect", &serial], &[])?;
+        expect_output(&output, &["connected to", "already connected to"])?;
+        Ok(serial)
+    }
+
+    /// Disconnects one `host:port` device, or every network device when `serial` is `None`.
+    pub fn disconnect(&self, serial: Option<&str>) -> Result<(), AdbError> {
+        let output = match serial {
+            Some(serial) => self.exec_unchecked(&["disconnect", serial], &[])?,
+            None => self.exec_unchecked(&["disconnect"], &[])?,
+        };
+        expect_output(&output, &["disconnected"]).map(|_| ())
+    }
+
+    /// Pairs with an Android 11+ device using the code shown in its wireless debugging
+    /// settings. `serial` is the `host:port` of the pairing service, not the debugging port.
+    pub fn pair(&self, serial: &str, pairing_code: &str) -> Result<(), AdbError> {
+        let output = self.exec_unchecked(&["pair", serial, pairing_code], &[2])?;
+        expect_output(&output, &["Successfully paired"]).map(|_| ())
+    }
+
+    /// Restarts adbd on the target listening on TCP `port`, after which it can be reached
+    /// with [`Adb::connect`].
+    pub fn tcpip(&self, port: u16) -> Result<(), AdbError> {
+        let port = port.to_string();
+        let output = self.exec_unchecked(&self.target_args(&["tcpip", &port]), &[])?;
+        expect_output(&output, &["restarting in TCP mode"]).map(|_| ())
+    }
+}
+
+/// Checks that the first line of output starts with one of `success_prefixes`, since these
+/// commands do not reliably signal failure through their exit status.
+fn expect_output(output: &CommandOutput, success_prefixes: &[&str]) -> Result<String, AdbError> {
+    let stdout = output.stdout_string();
+    let stderr = output.stderr_string();
+    let message = stdout
+        .lines()
+        .chain(stderr.lines())
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .unwrap_or_default()
+        .to_string();
+    if success_prefixes.iter().any(|prefix| message.starts_with(prefix)) {
+        return Ok(message);
+    }
+    Err(device_state_error(&message).unwrap_or(AdbError::CommandFailed(message)))
+}
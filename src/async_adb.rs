@@ -0,0 +1,335 @@
+use std::fmt::Debug;
+use std::future::{pending, Future};
+use std::io;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use image::{DynamicImage, RgbaImage};
+use tokio::process::Command;
+use tokio::time::sleep;
+use tracing::{debug, debug_span, trace, warn, Instrument};
+
+use crate::adb::{
+    check_activity_started,
+    check_output,
+    has_exclusive_active_target,
+    has_target,
+    loggable_args,
+    parse_display_size,
+    resolve_adb_path,
+    run_error,
+    DisplaySize,
+};
+use crate::device::{parse_devices, Device};
+use crate::error::AdbError;
+use crate::key_code::KeyCode;
+use crate::runner::{
+    CancellationToken,
+    CommandOutput,
+    CommandRunner,
+    RunOptions,
+    ScriptedRunner,
+};
+use crate::screencap::{parse_raw_screencap, CaptureMode};
+
+const CANCELLATION_POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+pub type CommandFuture<'a> = Pin<Box<dyn Future<Output = io::Result<CommandOutput>> + Send + 'a>>;
+
+/// The asynchronous counterpart of [`CommandRunner`], see its documentation for the
+/// contract around `args`, timeouts and cancellation.
+pub trait AsyncCommandRunner: Debug + Send + Sync {
+    fn run<'a>(&'a self, args: &'a [&'a str], options: &'a RunOptions) -> CommandFuture<'a>;
+}
+
+/// The default [`AsyncCommandRunner`], spawning the adb binary through `tokio::process`.
+#[derive(Clone, Debug)]
+pub struct TokioProcessRunner {
+    program: PathBuf,
+}
+
+impl TokioProcessRunner {
+    pub fn new(program: PathBuf) -> Self {
+        TokioProcessRunner { program }
+    }
+
+    pub fn program(&self) -> &PathBuf {
+        &self.program
+    }
+
+    async fn cancelled(options: &RunOptions) {
+        match &options.cancellation {
+            Some(cancellation) => {
+                while !cancellation.is_cancelled() {
+                    sleep(CANCELLATION_POLL_INTERVAL).await;
+                }
+            }
+            None => pending().await,
+        }
+    }
+
+    async fn expired(options: &RunOptions) {
+        match options.timeout {
+            Some(timeout) => sleep(timeout).await,
+            None => pending().await,
+        }
+    }
+}
+
+impl AsyncCommandRunner for TokioProcessRunner {
+    fn run<'a>(&'a self, args: &'a [&'a str], options: &'a RunOptions) -> CommandFuture<'a> {
+        Box::pin(async move {
+            trace!(program = %self.program.display(), "spawning process");
+            let started = Instant::now();
+            // Dropping the output future on timeout or cancellation kills the child.
+            let child = Command::new(&self.program)
+                .args(args)
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .kill_on_drop(true)
+                .spawn()?;
+            tokio::select! {
+                output = child.wait_with_output() => {
+                    let output = output?;
+                    Ok(CommandOutput {
+                        stdout: output.stdout,
+                        stderr: output.stderr,
+                        exit_code: output.status.code(),
+                        duration: started.elapsed(),
+                    })
+                }
+                _ = Self::cancelled(options) => {
+                    Err(io::Error::new(io::ErrorKind::Interrupted, "command cancelled"))
+                }
+                _ = Self::expired(options) => {
+                    Err(io::Error::new(io::ErrorKind::TimedOut, "command timed out"))
+                }
+            }
+        })
+    }
+}
+
+impl AsyncCommandRunner for ScriptedRunner {
+    fn run<'a>(&'a self, args: &'a [&'a str], options: &'a RunOptions) -> CommandFuture<'a> {
+        Box::pin(async move { CommandRunner::run(self, args, options) })
+    }
+}
+
+/// Asynchronous counterpart of [`Adb`](crate::adb::Adb) covering screen capture, input,
+/// display size and activity start, so many devices can be driven from one runtime.
+#[derive(Clone, Debug)]
+pub struct AsyncAdb {
+    runner: Arc<dyn AsyncCommandRunner>,
+    target_serial: Option<String>,
+    run_options: RunOptions,
+    redact_arguments: bool,
+    capture_mode: CaptureMode,
+}
+
+impl AsyncAdb {
+    pub async fn new(
+        adb_path: Option<PathBuf>, target_serial: Option<String>,
+    ) -> Result<Self, AdbError> {
+        let adb_path_buf = resolve_adb_path(adb_path)?;
+        Self::with_runner(
+            Arc::new(TokioProcessRunner::new(adb_path_buf)),
+            target_serial,
+        ).await
+    }
+
+    pub async fn with_runner(
+        runner: Arc<dyn AsyncCommandRunner>, target_serial: Option<String>,
+    ) -> Result<Self, AdbError> {
+        let adb = AsyncAdb {
+            runner,
+            target_serial: target_serial.clone(),
+            run_options: RunOptions::default(),
+            redact_arguments: true,
+            capture_mode: CaptureMode::default(),
+        };
+        let devices = adb.list_devices().await?;
+        match target_serial {
+            Some(provided_target_serial) => {
+                if !has_target(&devices, &provided_target_serial) {
+                    return Err(AdbError::TargetNotOnline);
+                }
+            }
+            None => {
+                if !has_exclusive_active_target(&devices) {
+                    return Err(AdbError::NoExclusiveTargetOnline);
+                }
+            }
+        }
+        adb.adb_no_target(&["start-server"]).await?;
+        Ok(adb)
+    }
+
+    pub fn target_serial(&self) -> Option<&str> {
+        self.target_serial.as_deref()
+    }
+
+    pub fn with_timeout(&self, timeout: Duration) -> AsyncAdb {
+        let mut adb = self.clone();
+        adb.run_options.timeout = Some(timeout);
+        adb
+    }
+
+    pub fn without_timeout(&self) -> AsyncAdb {
+        let mut adb = self.clone();
+        adb.run_options.timeout = None;
+        adb
+    }
+
+    pub fn with_cancellation(&self, cancellation: CancellationToken) -> AsyncAdb {
+        let mut adb = self.clone();
+        adb.run_options.cancellation = Some(cancellation);
+        adb
+    }
+
+    pub fn with_argument_redaction(&self, enabled: bool) -> AsyncAdb {
+        let mut adb = self.clone();
+        adb.redact_arguments = enabled;
+        adb
+    }
+
+    pub fn with_capture_mode(&self, capture_mode: CaptureMode) -> AsyncAdb {
+        let mut adb = self.clone();
+        adb.capture_mode = capture_mode;
+        adb
+    }
+
+    async fn adb_no_target(&self, args: &[&str]) -> Result<CommandOutput, AdbError> {
+        self.exec(args, &[]).await
+    }
+
+    async fn adb_target(&self, args: &[&str]) -> Result<CommandOutput, AdbError> {
+        self.adb_target_redacted(args, &[]).await
+    }
+
+    async fn adb_target_redacted(
+        &self, args: &[&str], sensitive: &[usize],
+    ) -> Result<CommandOutput, AdbError> {
+        let target_args = self.target_args(args);
+        let offset = target_args.len() - args.len();
+        self.exec(
+            &target_args,
+            &sensitive.iter().map(|index| index + offset).collect::<Vec<_>>(),
+        ).await
+    }
+
+    /// Prefixes `args` with `-s <serial>` when this instance has a target.
+    fn target_args<'a>(&'a self, args: &[&'a str]) -> Vec<&'a str> {
+        match &self.target_serial {
+            Some(target) => ["-s", target.as_str()]
+                .iter()
+                .cloned()
+                .chain(args.iter().cloned())
+                .collect(),
+            None => args.to_vec(),
+        }
+    }
+
+    async fn exec(&self, args: &[&str], sensitive: &[usize]) -> Result<CommandOutput, AdbError> {
+        let span = debug_span!(
+            "adb",
+            serial = self.target_serial.as_deref().unwrap_or_default(),
+            args = %loggable_args(args, sensitive, self.redact_arguments),
+        );
+        async move {
+            match self.runner.run(args, &self.run_options).await {
+                Ok(output) => {
+                    debug!(
+                        exit_code = ?output.exit_code,
+                        duration_ms = output.duration.as_millis() as u64,
+                        stdout_bytes = output.stdout.len(),
+                        stderr_bytes = output.stderr.len(),
+                        "adb command finished",
+                    );
+                    check_output(output)
+                }
+                Err(err) => {
+                    warn!(error = %err, "adb command did not complete");
+                    Err(run_error(err, &self.run_options))
+                }
+            }
+        }.instrument(span).await
+    }
+
+    pub async fn list_devices(&self) -> Result<Vec<Device>, AdbError> {
+        self.adb_no_target(&["devices", "-l"])
+            .await
+            .map(|output| parse_devices(&output.stdout_string()))
+    }
+
+    pub async fn display_size(&self) -> Result<DisplaySize, AdbError> {
+        parse_display_size(self.adb_target(&["shell", "wm", "size"]).await?.stdout_string())
+    }
+
+    pub async fn capture_screen_as_bytes(&self) -> Result<Vec<u8>, AdbError> {
+        self.adb_target(&["exec-out", "screencap", "-p"])
+            .await
+            .map(|output| output.stdout)
+    }
+
+    /// Captures the screen in this instance's [`CaptureMode`] and decodes it on the blocking
+    /// thread pool.
+    pub async fn capture_screen_as_rgba_image(&self) -> Result<RgbaImage, AdbError> {
+        let output = self.adb_target(self.capture_mode.args()).await?;
+        let capture_mode = self.capture_mode;
+        tokio::task::spawn_blocking(move || match capture_mode {
+            CaptureMode::Png => image::load_from_memory(&output.stdout)
+                .map(|image| image.into_rgba8())
+                .map_err(|err| AdbError::UnparseableOutput(err.to_string())),
+            CaptureMode::Raw => parse_raw_screencap(&output.stdout).map(|raw| raw.image),
+        })
+            .await
+            .map_err(|err| AdbError::UnparseableOutput(err.to_string()))?
+    }
+
+    pub async fn capture_screen_as_dynamic_image(&self) -> Result<DynamicImage, AdbError> {
+        self.capture_screen_as_rgba_image().await.map(DynamicImage::ImageRgba8)
+    }
+
+    pub async fn start_activity(&self, activity_component_name: &str) -> Result<(), AdbError> {
+        check_activity_started(&self.adb_target(
+            &["shell", "am", "start", "-n", activity_component_name],
+        ).await?)
+    }
+
+    pub async fn input_text(&self, text: &str) -> Result<(), AdbError> {
+        self.adb_target_redacted(&["shell", "input", "text", text], &[3])
+            .await
+            .map(|_| ())
+    }
+
+    pub async fn input_key_event(&self, key_code: KeyCode) -> Result<(), AdbError> {
+        self.adb_target(&["shell", "input", "keyevent", &*(key_code as u32).to_string()])
+            .await
+            .map(|_| ())
+    }
+
+    pub async fn input_tap(&self, x: u32, y: u32) -> Result<(), AdbError> {
+        self.adb_target(&["shell", "input", "tap", &x.to_string(), &y.to_string()])
+            .await
+            .map(|_| ())
+    }
+
+    pub async fn input_swipe(
+        &self,
+        x_start: u32, x_end: u32,
+        y_start: u32, y_end: u32,
+        duration: u32,
+    ) -> Result<(), AdbError> {
+        self.adb_target(&[
+            "shell", "input", "touchscreen", "swipe",
+            &x_start.to_string(),
+            &y_start.to_string(),
+            &x_end.to_string(),
+            &y_end.to_string(),
+            &duration.to_string()
+        ]).await.map(|_| ())
+    }
+}
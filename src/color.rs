@@ -0,0 +1,123 @@
+use image::DynamicImage;
+
+use crate::geometry::{Point, Rect};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+/// Hue in degrees from 0.0 to 360.0, saturation and value from 0.0 to 1.0.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Hsv {
+    pub hue: f64,
+    pub saturation: f64,
+    pub value: f64,
+}
+
+impl Color {
+    pub fn new(r: u8, g: u8, b: u8) -> Self {
+        Color { r, g, b }
+    }
+
+    /// Euclidean distance in RGB space, from 0.0 to about 441.7 (black to white).
+    pub fn distance(&self, other: &Color) -> f64 {
+        let channel = |a: u8, b: u8| (a as f64 - b as f64).powi(2);
+        (channel(self.r, other.r) + channel(self.g, other.g) + channel(self.b, other.b)).sqrt()
+    }
+
+    pub fn to_hsv(&self) -> Hsv {
+        let (r, g, b) = (self.r as f64 / 255.0, self.g as f64 / 255.0, self.b as f64 / 255.0);
+        let max = r.max(g).max(b);
+        let delta = max - r.min(g).min(b);
+        let hue = if delta == 0.0 {
+            0.0
+        } else if max == r {
+            60.0 * ((g - b) / delta).rem_euclid(6.0)
+        } else if max == g {
+            60.0 * ((b - r) / delta + 2.0)
+        } else {
+            60.0 * ((r - g) / delta + 4.0)
+        };
+        Hsv {
+            hue,
+            saturation: if max == 0.0 { 0.0 } else { delta / max },
+            value: max,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ColorTolerance {
+    /// Maximum [`Color::distance`].
+    Rgb(f64),
+    /// Maximum difference per HSV component, with hue compared around the color wheel. Hue
+    /// is ignored for grays, which have none. Copes better than RGB with lighting effects
+    /// such as a pressed button darkening.
+    Hsv {
+        hue: f64,
+        saturation: f64,
+        value: f64,
+    },
+}
+
+impl ColorTolerance {
+    pub fn matches(&self, expected: &Color, actual: &Color) -> bool {
+        match self {
+            ColorTolerance::Rgb(distance) => expected.distance(actual) <= *distance,
+            ColorTolerance::Hsv { hue, saturation, value } => {
+                let (expected, actual) = (expected.to_hsv(), actual.to_hsv());
+                let hue_difference = (expected.hue - actual.hue).abs();
+                let hue_difference = hue_difference.min(360.0 - hue_difference);
+                let achromatic = expected.saturation == 0.0 || actual.saturation == 0.0;
+                (achromatic || hue_difference <= *hue)
+                    && (expected.saturation - actual.saturation).abs() <= *saturation
+                    && (expected.value - actual.value).abs() <= *value
+            }
+        }
+    }
+}
+
+/// Expected colors at several points, which together identify a screen or widget state more
+/// reliably than any single pixel.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ColorSignature {
+    pub probes: Vec<(Point, Color)>,
+    pub tolerance: ColorTolerance,
+}
+
+impl ColorSignature {
+    pub fn new(probes: Vec<(Point, Color)>, tolerance: ColorTolerance) -> Self {
+        ColorSignature { probes, tolerance }
+    }
+
+    /// Whether every probe matches, `false` if any lies outside the image.
+    pub fn matches(&self, dynamic_image: &DynamicImage) -> bool {
+        self.probes.iter().all(|(point, expected)| {
+            pixel_at(dynamic_image, *point)
+                .is_some_and(|actual| self.tolerance.matches(expected, &actual))
+        })
+    }
+}
+
+pub fn pixel_at(dynamic_image: &DynamicImage, point: Point) -> Option<Color> {
+    average_color(dynamic_image, Rect::new(point.x, point.y, 1, 1))
+}
+
+/// The mean color within `rect`, clipped to the image, or `None` if it lies outside.
+pub fn average_color(dynamic_image: &DynamicImage, rect: Rect) -> Option<Color> {
+    let bounds = Rect::new(0, 0, dynamic_image.width(), dynamic_image.height());
+    let rect = rect.intersection(&bounds)?;
+    let rgb = dynamic_image.crop_imm(rect.x, rect.y, rect.width, rect.height).into_rgb8();
+    let mut sums = [0u64; 3];
+    for pixel in rgb.pixels() {
+        for (sum, channel) in sums.iter_mut().zip(pixel.0) {
+            *sum += channel as u64;
+        }
+    }
+    let count = rect.area();
+    let mean = |sum: u64| ((sum + count / 2) / count) as u8;
+    Some(Color::new(mean(sums[0]), mean(sums[1]), mean(sums[2])))
+}
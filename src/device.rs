@@ -0,0 +1,178 @@
+use std::fmt;
+use std::fmt::Display;
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum DeviceState {
+    Device,
+    Offline,
+    Unauthorized,
+    Authorizing,
+    Connecting,
+    NoPermissions,
+    Recovery,
+    Rescue,
+    Sideload,
+    Bootloader,
+    Host,
+    Unknown(String),
+}
+
+impl DeviceState {
+    pub fn parse(state: &str) -> Self {
+        match state {
+            "device" => DeviceState::Device,
+            "offline" => DeviceState::Offline,
+            "unauthorized" => DeviceState::Unauthorized,
+            "authorizing" => DeviceState::Authorizing,
+            "connecting" => DeviceState::Connecting,
+            "no permissions" => DeviceState::NoPermissions,
+            "recovery" => DeviceState::Recovery,
+            "rescue" => DeviceState::Rescue,
+            "sideload" => DeviceState::Sideload,
+            "bootloader" => DeviceState::Bootloader,
+            "host" => DeviceState::Host,
+            other => DeviceState::Unknown(other.to_string()),
+        }
+    }
+}
+
+impl Display for DeviceState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeviceState::Device => write!(f, "device"),
+            DeviceState::Offline => write!(f, "offline"),
+            DeviceState::Unauthorized => write!(f, "unauthorized"),
+            DeviceState::Authorizing => write!(f, "authorizing"),
+            DeviceState::Connecting => write!(f, "connecting"),
+            DeviceState::NoPermissions => write!(f, "no permissions"),
+            DeviceState::Recovery => write!(f, "recovery"),
+            DeviceState::Rescue => write!(f, "rescue"),
+            DeviceState::Sideload => write!(f, "sideload"),
+            DeviceState::Bootloader => write!(f, "bootloader"),
+            DeviceState::Host => write!(f, "host"),
+            DeviceState::Unknown(state) => write!(f, "{}", state),
+        }
+    }
+}
+
+/// A state to wait for with [`Adb::wait_for_state`](crate::adb::Adb::wait_for_state).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WaitState {
+    Device,
+    Recovery,
+    Rescue,
+    Sideload,
+    /// Only reached by bootloaders that speak adb, most are visible to fastboot alone.
+    Bootloader,
+    Disconnect,
+}
+
+impl WaitState {
+    pub(crate) fn device_state(&self) -> Option<DeviceState> {
+        match self {
+            WaitState::Device => Some(DeviceState::Device),
+            WaitState::Recovery => Some(DeviceState::Recovery),
+            WaitState::Rescue => Some(DeviceState::Rescue),
+            WaitState::Sideload => Some(DeviceState::Sideload),
+            WaitState::Bootloader => Some(DeviceState::Bootloader),
+            WaitState::Disconnect => None,
+        }
+    }
+}
+
+/// A mode to reboot into with [`Adb::reboot`](crate::adb::Adb::reboot).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RebootMode {
+    System,
+    Bootloader,
+    Recovery,
+    Sideload,
+    /// Sideload mode that reboots back to the system once the sideload finishes.
+    SideloadAutoReboot,
+    /// The userspace fastboot implementation (fastbootd) of dynamic partition devices.
+    Fastboot,
+}
+
+impl RebootMode {
+    pub(crate) fn argument(&self) -> Option<&'static str> {
+        match self {
+            RebootMode::System => None,
+            RebootMode::Bootloader => Some("bootloader"),
+            RebootMode::Recovery => Some("recovery"),
+            RebootMode::Sideload => Some("sideload"),
+            RebootMode::SideloadAutoReboot => Some("sideload-auto-reboot"),
+            RebootMode::Fastboot => Some("fastboot"),
+        }
+    }
+
+    /// The state adb reports once the device is back, or `None` for modes only visible to
+    /// fastboot.
+    pub(crate) fn wait_state(&self) -> Option<WaitState> {
+        match self {
+            RebootMode::System => Some(WaitState::Device),
+            RebootMode::Recovery => Some(WaitState::Recovery),
+            RebootMode::Sideload | RebootMode::SideloadAutoReboot => Some(WaitState::Sideload),
+            RebootMode::Bootloader => Some(WaitState::Bootloader),
+            RebootMode::Fastboot => None,
+        }
+    }
+}
+
+/// A device as listed by `adb devices -l`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Device {
+    pub serial: String,
+    pub state: DeviceState,
+    pub product: Option<String>,
+    pub model: Option<String>,
+    pub device: Option<String>,
+    pub transport_id: Option<u64>,
+}
+
+impl Device {
+    pub fn is_online(&self) -> bool {
+        self.state == DeviceState::Device
+    }
+
+    /// Parses one line of `adb devices -l`, returning `None` for headers and blank lines.
+    pub fn parse(line: &str) -> Option<Self> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with("List of devices") || line.starts_with('*') {
+            return None;
+        }
+        let (serial, rest) = line.split_once(char::is_whitespace)?;
+        let rest = rest.trim_start();
+        // "no permissions" is the only state containing whitespace, and it is followed by a
+        // free-form explanation rather than key:value pairs.
+        if rest.starts_with("no permissions") {
+            return Some(Device::new(serial, DeviceState::NoPermissions));
+        }
+        let mut fields = rest.split_whitespace();
+        let mut device = Device::new(serial, DeviceState::parse(fields.next()?));
+        for field in fields {
+            match field.split_once(':') {
+                Some(("product", value)) => device.product = Some(value.to_string()),
+                Some(("model", value)) => device.model = Some(value.to_string()),
+                Some(("device", value)) => device.device = Some(value.to_string()),
+                Some(("transport_id", value)) => device.transport_id = value.parse().ok(),
+                _ => {}
+            }
+        }
+        Some(device)
+    }
+
+    fn new(serial: &str, state: DeviceState) -> Self {
+        Device {
+            serial: serial.to_string(),
+            state,
+            product: None,
+            model: None,
+            device: None,
+            transport_id: None,
+        }
+    }
+}
+
+pub(crate) fn parse_devices(output: &str) -> Vec<Device> {
+    output.lines().filter_map(Device::parse).collect()
+}
@@ -0,0 +1,203 @@
+use std::path::PathBuf;
+
+use image::{DynamicImage, Rgba, RgbaImage};
+
+use crate::error::ImageSaveError;
+use crate::geometry::Rect;
+use crate::utils::save_dynamic_image_as_png;
+
+/// Changed pixels this close to each other are reported as one region.
+const REGION_MERGE_DISTANCE: u32 = 4;
+const MISMATCH_COLOR: Rgba<u8> = Rgba([255, 0, 0, 255]);
+const ANTI_ALIASED_COLOR: Rgba<u8> = Rgba([255, 200, 0, 255]);
+
+#[derive(Clone, Debug, Default)]
+pub struct DiffOptions {
+    /// Largest per-channel difference still considered equal.
+    pub tolerance: u8,
+    /// Regions never compared, e.g. the status bar or a video view.
+    pub masks: Vec<Rect>,
+    /// Whether differences along edges that look like shifted anti-aliasing count as equal.
+    pub ignore_anti_aliasing: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct ImageDiff {
+    pub mismatched_pixels: u64,
+    /// Pixels compared, excluding masked ones.
+    pub compared_pixels: u64,
+    /// The baseline faded to gray with mismatches in red and ignored anti-aliasing in yellow.
+    pub diff_image: RgbaImage,
+    /// Bounding boxes of the changed areas, top to bottom.
+    pub regions: Vec<Rect>,
+}
+
+impl ImageDiff {
+    pub fn is_match(&self) -> bool {
+        self.mismatched_pixels == 0
+    }
+
+    /// Mismatched pixels from 0.0 to 100.0 percent of those compared.
+    pub fn mismatch_percentage(&self) -> f64 {
+        match self.compared_pixels {
+            0 => 0.0,
+            compared => self.mismatched_pixels as f64 * 100.0 / compared as f64,
+        }
+    }
+
+    /// Saves `<prefix>_baseline.png`, `<prefix>_actual.png` and `<prefix>_diff.png`, returning
+    /// their paths in that order.
+    pub fn save(
+        &self, baseline: &DynamicImage, actual: &DynamicImage, prefix: &str,
+    ) -> Result<[PathBuf; 3], ImageSaveError> {
+        Ok([
+            save_dynamic_image_as_png(baseline, &format!("{}_baseline.png", prefix))?,
+            save_dynamic_image_as_png(actual, &format!("{}_actual.png", prefix))?,
+            save_dynamic_image_as_png(
+                &DynamicImage::ImageRgba8(self.diff_image.clone()),
+                &format!("{}_diff.png", prefix),
+            )?,
+        ])
+    }
+}
+
+/// Compares `actual` against `baseline` pixel by pixel.
+///
+/// Images of different sizes are compared over the larger extent, with pixels present in
+/// only one of them counted as mismatches.
+pub fn diff_images(
+    baseline: &DynamicImage, actual: &DynamicImage, options: &DiffOptions,
+) -> ImageDiff {
+    let (baseline, actual) = (baseline.to_rgba8(), actual.to_rgba8());
+    let width = baseline.width().max(actual.width());
+    let height = baseline.height().max(actual.height());
+    let mut diff_image = RgbaImage::new(width, height);
+    let mut mismatches = vec![false; width as usize * height as usize];
+    let (mut mismatched_pixels, mut compared_pixels) = (0, 0);
+    for y in 0..height {
+        for x in 0..width {
+            let expected = baseline.get_pixel_checked(x, y);
+            diff_image.put_pixel(x, y, faded(expected));
+            if options.masks.iter().any(|mask| mask.contains(x, y)) {
+                continue;
+            }
+            compared_pixels += 1;
+            let (Some(expected), Some(found)) = (expected, actual.get_pixel_checked(x, y)) else {
+                mismatched_pixels += 1;
+                mismatches[(y * width + x) as usize] = true;
+                diff_image.put_pixel(x, y, MISMATCH_COLOR);
+                continue;
+            };
+            if channels_within(expected, found, options.tolerance) {
+                continue;
+            }
+            if options.ignore_anti_aliasing
+                && (is_anti_aliased(&baseline, &actual, x, y, options.tolerance)
+                    && is_anti_aliased(&actual, &baseline, x, y, options.tolerance))
+            {
+                diff_image.put_pixel(x, y, ANTI_ALIASED_COLOR);
+                continue;
+            }
+            mismatched_pixels += 1;
+            mismatches[(y * width + x) as usize] = true;
+            diff_image.put_pixel(x, y, MISMATCH_COLOR);
+        }
+    }
+    ImageDiff {
+        mismatched_pixels,
+        compared_pixels,
+        diff_image,
+        regions: changed_regions(&mismatches, width, height),
+    }
+}
+
+fn faded(pixel: Option<&Rgba<u8>>) -> Rgba<u8> {
+    let luma = pixel.map_or(0, |Rgba([r, g, b, _])| {
+        (*r as u32 * 299 + *g as u32 * 587 + *b as u32 * 114) / 1000
+    });
+    let value = (255 - (255 - luma) / 4) as u8;
+    Rgba([value, value, value, 255])
+}
+
+fn channels_within(a: &Rgba<u8>, b: &Rgba<u8>, tolerance: u8) -> bool {
+    a.0.iter().zip(b.0).all(|(a, b)| a.abs_diff(b) <= tolerance)
+}
+
+/// Whether the pixel at `x`/`y` of `image` could come from an edge of `other` shifted or
+/// blended by up to a pixel: the 3x3 neighbourhood of `other` has an edge and each channel
+/// of the pixel lies within the range that neighbourhood spans. A real change such as a new
+/// widget fails this in at least one direction.
+fn is_anti_aliased(image: &RgbaImage, other: &RgbaImage, x: u32, y: u32, tolerance: u8) -> bool {
+    let pixel = image.get_pixel(x, y);
+    let (mut low, mut high) = ([u8::MAX; 4], [u8::MIN; 4]);
+    for ny in y.saturating_sub(1)..=(y + 1).min(other.height() - 1) {
+        for nx in x.saturating_sub(1)..=(x + 1).min(other.width() - 1) {
+            for (channel, value) in other.get_pixel(nx, ny).0.iter().enumerate() {
+                low[channel] = low[channel].min(*value);
+                high[channel] = high[channel].max(*value);
+            }
+        }
+    }
+    let has_edge = (0..4).any(|channel| high[channel] - low[channel] > tolerance);
+    has_edge
+        && (0..4).all(|channel| {
+            pixel[channel] >= low[channel].saturating_sub(tolerance)
+                && pixel[channel] <= high[channel].saturating_add(tolerance)
+        })
+}
+
+/// Bounding boxes of connected mismatches, merging boxes closer than
+/// [`REGION_MERGE_DISTANCE`].
+fn changed_regions(mismatches: &[bool], width: u32, height: u32) -> Vec<Rect> {
+    let mut visited = vec![false; mismatches.len()];
+    let mut regions: Vec<Rect> = Vec::new();
+    for start in 0..mismatches.len() {
+        if !mismatches[start] || visited[start] {
+            continue;
+        }
+        visited[start] = true;
+        let (start_x, start_y) = (start as u32 % width, start as u32 / width);
+        let (mut left, mut top, mut right, mut bottom) = (start_x, start_y, start_x, start_y);
+        let mut stack = vec![(start_x, start_y)];
+        while let Some((x, y)) = stack.pop() {
+            (left, top, right, bottom) = (left.min(x), top.min(y), right.max(x), bottom.max(y));
+            for ny in y.saturating_sub(1)..=(y + 1).min(height - 1) {
+                for nx in x.saturating_sub(1)..=(x + 1).min(width - 1) {
+                    let index = (ny * width + nx) as usize;
+                    if mismatches[index] && !visited[index] {
+                        visited[index] = true;
+                        stack.push((nx, ny));
+                    }
+                }
+            }
+        }
+        regions.push(Rect::new(left, top, right - left + 1, bottom - top + 1));
+    }
+    let grown = |rect: &Rect| {
+        let x = rect.x.saturating_sub(REGION_MERGE_DISTANCE);
+        let y = rect.y.saturating_sub(REGION_MERGE_DISTANCE);
+        let right = rect.right() + REGION_MERGE_DISTANCE;
+        Rect::new(x, y, right - x, rect.bottom() + REGION_MERGE_DISTANCE - y)
+    };
+    let mut merged = true;
+    while merged {
+        merged = false;
+        'search: for i in 0..regions.len() {
+            for j in i + 1..regions.len() {
+                if grown(&regions[i]).intersects(&regions[j]) {
+                    let (a, b) = (regions[i], regions.remove(j));
+                    regions[i] = Rect::new(
+                        a.x.min(b.x),
+                        a.y.min(b.y),
+                        a.right().max(b.right()) - a.x.min(b.x),
+                        a.bottom().max(b.bottom()) - a.y.min(b.y),
+                    );
+                    merged = true;
+                    break 'search;
+                }
+            }
+        }
+    }
+    regions.sort_by_key(|region| (region.y, region.x));
+    regions
+}
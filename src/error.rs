@@ -1,57 +1,72 @@
 use std::{fmt, io};
 use std::error::Error;
 use std::fmt::Display;
+use std::time::Duration;
+
+use crate::geometry::Rect;
 
 #[derive(Debug)]
 pub enum AdbError {
     AdbNotFound,
     TargetNotOnline,
     NoExclusiveTargetOnline,
+    Spawn(io::Error),
+    NonZeroExit {
+        exit_code: Option<i32>,
+        stderr: String,
+    },
+    DeviceOffline,
+    DeviceUnauthorized,
+    UnparseableOutput(String),
+    CommandFailed(String),
+    ImageSave(ImageSaveError),
+    Timeout(Duration),
+    Cancelled,
+    Io(io::Error),
+    ServerFailure(String),
+    RegionOutOfBounds(Rect),
 }
 
 impl Display for AdbError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match *self {
+        match self {
             AdbError::AdbNotFound => write!(f, "ADB binary not found"),
             AdbError::TargetNotOnline => write!(f, "Provided target is not online"),
             AdbError::NoExclusiveTargetOnline => write!(f, "No exclusive target online"),
+            AdbError::Spawn(err) => write!(f, "Failed to spawn ADB: {}", err),
+            AdbError::NonZeroExit { exit_code: Some(code), stderr } => {
+                write!(f, "ADB exited with status {}: {}", code, stderr)
+            }
+            AdbError::NonZeroExit { exit_code: None, stderr } => {
+                write!(f, "ADB terminated by signal: {}", stderr)
+            }
+            AdbError::DeviceOffline => write!(f, "Device is offline"),
+            AdbError::DeviceUnauthorized => write!(f, "Device is unauthorized"),
+            AdbError::UnparseableOutput(output) => write!(f, "Unparseable ADB output: {}", output),
+            AdbError::CommandFailed(message) => write!(f, "Command failed: {}", message),
+            AdbError::ImageSave(err) => write!(f, "Failed to save image: {}", err),
+            AdbError::Timeout(timeout) => write!(f, "ADB command timed out after {:?}", timeout),
+            AdbError::Cancelled => write!(f, "ADB command cancelled"),
+            AdbError::Io(err) => write!(f, "IO error: {}", err),
+            AdbError::ServerFailure(message) => write!(f, "ADB server failure: {}", message),
+            AdbError::RegionOutOfBounds(region) => {
+                write!(f, "Region {:?} lies outside the screen", region)
+            }
         }
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct DisplaySizeError;
-
-impl Display for DisplaySizeError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "Failed to get display size")
-    }
-}
-
-impl Error for DisplaySizeError {}
-
-#[derive(Debug, Clone)]
-pub struct CaptureScreenError;
-
-impl Display for CaptureScreenError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "Failed to capture screen")
-    }
-}
-
-impl Error for CaptureScreenError {}
-
-#[derive(Debug, Clone)]
-pub struct ScreenSumError;
-
-impl Display for ScreenSumError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "Failed to calculate screen sum")
+impl Error for AdbError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            AdbError::Spawn(err) => Some(err),
+            AdbError::Io(err) => Some(err),
+            AdbError::ImageSave(err) => Some(err),
+            _ => None,
+        }
     }
 }
 
-impl Error for ScreenSumError {}
-
 #[derive(Debug)]
 pub enum ImageSaveError {
     Io(io::Error),
@@ -0,0 +1,195 @@
+use std::sync::mpsc;
+use std::sync::mpsc::{Receiver, SyncSender, TrySendError};
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime};
+
+use image::RgbaImage;
+
+use crate::adb::Adb;
+use crate::error::AdbError;
+use crate::geometry::Rect;
+use crate::runner::CancellationToken;
+
+const SLEEP_SLICE: Duration = Duration::from_millis(20);
+
+#[derive(Clone, Debug)]
+pub struct FrameStreamOptions {
+    /// Captures per second to aim for; the device may not keep up.
+    pub fps: f64,
+    /// Frames held for a slow consumer before new ones are dropped.
+    pub buffer: usize,
+    /// Regions ignored when deciding whether a frame changed, e.g. the status bar clock.
+    pub masks: Vec<Rect>,
+}
+
+impl Default for FrameStreamOptions {
+    fn default() -> Self {
+        FrameStreamOptions {
+            fps: 2.0,
+            buffer: 4,
+            masks: Vec::new(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Frame {
+    pub image: RgbaImage,
+    pub captured_at: SystemTime,
+    /// Position of this frame among all captures, including dropped ones.
+    pub sequence: u64,
+    /// Whether any unmasked pixel differs from the previously delivered frame. Always `true`
+    /// for the first frame.
+    pub changed: bool,
+    /// Frames dropped since the previously delivered frame because the buffer was full.
+    pub dropped: u64,
+}
+
+/// Screenshots captured at a steady rate on a background thread, see [`Adb::frame_stream`].
+///
+/// The iterator ends after the first capture error, yielding the error last. Dropping the
+/// stream or calling [`FrameStream::stop`] aborts the capture in flight.
+pub struct FrameStream {
+    receiver: Receiver<Result<Frame, AdbError>>,
+    cancellation: CancellationToken,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl FrameStream {
+    pub(crate) fn start(adb: &Adb, options: FrameStreamOptions) -> Result<Self, AdbError> {
+        let cancellation = CancellationToken::new();
+        let adb = adb.with_cancellation(cancellation.clone());
+        let (sender, receiver) = mpsc::sync_channel(options.buffer.max(1));
+        let stream_cancellation = cancellation.clone();
+        let handle = thread::Builder::new()
+            .name("adb-frame-stream".to_string())
+            .spawn(move || Self::run(adb, options, sender, stream_cancellation))
+            .map_err(AdbError::Io)?;
+        Ok(FrameStream {
+            receiver,
+            cancellation,
+            handle: Some(handle),
+        })
+    }
+
+    fn run(
+        adb: Adb,
+        options: FrameStreamOptions,
+        sender: SyncSender<Result<Frame, AdbError>>,
+        cancellation: CancellationToken,
+    ) {
+        let interval = Duration::from_secs_f64(1.0 / options.fps.max(f64::MIN_POSITIVE));
+        let mut previous: Option<RgbaImage> = None;
+        let mut dropped = 0;
+        let mut next_capture = Instant::now();
+        for sequence in 0.. {
+            if !sleep_until(next_capture, &cancellation) {
+                return;
+            }
+            next_capture = Instant::now() + interval;
+            let image = match adb.capture_screen_as_rgba_image() {
+                Ok(image) => image,
+                Err(_) if cancellation.is_cancelled() => return,
+                Err(err) => {
+                    send_error(&sender, err, &cancellation);
+                    return;
+                }
+            };
+            let frame = Frame {
+                changed: previous
+                    .as_ref()
+                    .is_none_or(|previous| differs(previous, &image, &options.masks)),
+                image,
+                captured_at: SystemTime::now(),
+                sequence,
+                dropped,
+            };
+            let image = frame.image.clone();
+            match sender.try_send(Ok(frame)) {
+                Ok(()) => {
+                    previous = Some(image);
+                    dropped = 0;
+                }
+                Err(TrySendError::Full(_)) => dropped += 1,
+                Err(TrySendError::Disconnected(_)) => return,
+            }
+        }
+    }
+
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<Result<Frame, AdbError>> {
+        self.receiver.recv_timeout(timeout).ok()
+    }
+
+    pub fn try_recv(&self) -> Option<Result<Frame, AdbError>> {
+        self.receiver.try_recv().ok()
+    }
+
+    pub fn stop(mut self) {
+        self.shutdown();
+    }
+
+    fn shutdown(&mut self) {
+        self.cancellation.cancel();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Iterator for FrameStream {
+    type Item = Result<Frame, AdbError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.receiver.recv().ok()
+    }
+}
+
+impl Drop for FrameStream {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+/// Sleeps until `deadline` in short slices, returning `false` if cancelled meanwhile.
+fn sleep_until(deadline: Instant, cancellation: &CancellationToken) -> bool {
+    loop {
+        if cancellation.is_cancelled() {
+            return false;
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            return true;
+        }
+        thread::sleep(SLEEP_SLICE.min(deadline - now));
+    }
+}
+
+/// Delivers the final error without blocking a [`FrameStream::stop`] waiting on this thread
+/// while the buffer is full.
+fn send_error(
+    sender: &SyncSender<Result<Frame, AdbError>>, err: AdbError, cancellation: &CancellationToken,
+) {
+    let mut item = Err(err);
+    loop {
+        match sender.try_send(item) {
+            Err(TrySendError::Full(returned)) if !cancellation.is_cancelled() => {
+                item = returned;
+                thread::sleep(SLEEP_SLICE);
+            }
+            _ => return,
+        }
+    }
+}
+
+fn differs(previous: &RgbaImage, current: &RgbaImage, masks: &[Rect]) -> bool {
+    if previous.dimensions() != current.dimensions() {
+        return true;
+    }
+    previous
+        .enumerate_pixels()
+        .zip(current.pixels())
+        .any(|((x, y, before), after)| {
+            before != after && !masks.iter().any(|mask| mask.contains(x, y))
+        })
+}
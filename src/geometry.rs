@@ -0,0 +1,106 @@
+/// A position in screen pixels.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Point {
+    pub x: u32,
+    pub y: u32,
+}
+
+impl Point {
+    pub fn new(x: u32, y: u32) -> Self {
+        Point { x, y }
+    }
+
+    /// Moves this point by `x`/`y`, e.g. from region-relative to full-screen coordinates,
+    /// saturating at `u32::MAX`.
+    pub fn offset(&self, x: u32, y: u32) -> Point {
+        Point::new(self.x.saturating_add(x), self.y.saturating_add(y))
+    }
+}
+
+/// An axis-aligned rectangle in screen pixels, with `x`/`y` the top-left corner.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    pub fn new(x: u32, y: u32, width: u32, height: u32) -> Self {
+        Rect { x, y, width, height }
+    }
+
+    /// The rectangle spanning `top_left` inclusive to `bottom_right` exclusive.
+    pub fn from_corners(top_left: Point, bottom_right: Point) -> Self {
+        Rect::new(
+            top_left.x,
+            top_left.y,
+            bottom_right.x.saturating_sub(top_left.x),
+            bottom_right.y.saturating_sub(top_left.y),
+        )
+    }
+
+    pub fn top_left(&self) -> Point {
+        Point::new(self.x, self.y)
+    }
+
+    /// The first point past the rectangle on both axes.
+    pub fn bottom_right(&self) -> Point {
+        Point::new(self.right(), self.bottom())
+    }
+
+    pub fn right(&self) -> u32 {
+        self.x.saturating_add(self.width)
+    }
+
+    pub fn bottom(&self) -> u32 {
+        self.y.saturating_add(self.height)
+    }
+
+    pub fn center(&self) -> Point {
+        Point::new(
+            self.x.saturating_add(self.width / 2),
+            self.y.saturating_add(self.height / 2),
+        )
+    }
+
+    pub fn area(&self) -> u64 {
+        self.width as u64 * self.height as u64
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
+    pub fn contains(&self, x: u32, y: u32) -> bool {
+        x >= self.x && y >= self.y && x - self.x < self.width && y - self.y < self.height
+    }
+
+    pub fn contains_point(&self, point: Point) -> bool {
+        self.contains(point.x, point.y)
+    }
+
+    /// Whether `other` lies entirely within this rectangle.
+    pub fn contains_rect(&self, other: &Rect) -> bool {
+        other.x >= self.x
+            && other.y >= self.y
+            && other.right() <= self.right()
+            && other.bottom() <= self.bottom()
+    }
+
+    /// The overlapping area of both rectangles, `None` if they do not overlap.
+    pub fn intersection(&self, other: &Rect) -> Option<Rect> {
+        let top_left = Point::new(self.x.max(other.x), self.y.max(other.y));
+        let bottom_right = Point::new(
+            self.right().min(other.right()),
+            self.bottom().min(other.bottom()),
+        );
+        let intersection = Rect::from_corners(top_left, bottom_right);
+        (!intersection.is_empty()).then_some(intersection)
+    }
+
+    pub fn intersects(&self, other: &Rect) -> bool {
+        self.intersection(other).is_some()
+    }
+}
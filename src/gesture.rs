@@ -0,0 +1,200 @@
+use std::f64::consts::PI;
+use std::time::Duration;
+
+use crate::geometry::Point;
+
+/// Largest angle a [`Gesture::rotate`] finger turns between waypoints, in degrees.
+const ROTATION_STEP_DEGREES: f64 = 10.0;
+
+/// One finger's path: it touches down at the first waypoint, moves in straight lines between
+/// waypoints and lifts at the last one. Times are offsets from the start of the gesture.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PointerPath {
+    waypoints: Vec<(Duration, Point)>,
+}
+
+impl PointerPath {
+    /// A finger touching down at `start` as the gesture begins.
+    pub fn new(start: Point) -> Self {
+        PointerPath::starting_after(Duration::ZERO, start)
+    }
+
+    /// A finger touching down at `start` once `delay` has passed.
+    pub fn starting_after(delay: Duration, start: Point) -> Self {
+        PointerPath { waypoints: vec![(delay, start)] }
+    }
+
+    /// Moves in a straight line to `point`, arriving after `duration`.
+    pub fn move_to(mut self, point: Point, duration: Duration) -> Self {
+        self.waypoints.push((self.end() + duration, point));
+        self
+    }
+
+    /// Follows `points` at constant speed, taking `duration` in total.
+    pub fn follow(mut self, points: &[Point], duration: Duration) -> Self {
+        let mut previous = self.waypoints.last().unwrap().1;
+        let lengths: Vec<f64> = points
+            .iter()
+            .map(|point| {
+                let length = distance(previous, *point);
+                previous = *point;
+                length
+            })
+            .collect();
+        let total: f64 = lengths.iter().sum();
+        let start = self.end();
+        let mut travelled = 0.0;
+        for (point, length) in points.iter().zip(lengths) {
+            travelled += length;
+            let fraction = if total > 0.0 { travelled / total } else { 1.0 };
+            self.waypoints.push((start + duration.mul_f64(fraction), *point));
+        }
+        self
+    }
+
+    /// Stays down where the finger is for `duration`.
+    pub fn hold(self, duration: Duration) -> Self {
+        let point = self.waypoints.last().unwrap().1;
+        self.move_to(point, duration)
+    }
+
+    pub fn waypoints(&self) -> &[(Duration, Point)] {
+        &self.waypoints
+    }
+
+    /// When the finger touches down.
+    pub fn start(&self) -> Duration {
+        self.waypoints[0].0
+    }
+
+    /// When the finger lifts.
+    pub fn end(&self) -> Duration {
+        self.waypoints.last().unwrap().0
+    }
+
+    /// Where the finger is at `time`, `None` while it is not touching.
+    pub fn position_at(&self, time: Duration) -> Option<Point> {
+        if time < self.start() || time > self.end() {
+            return None;
+        }
+        let index = self.waypoints.iter().position(|(at, _)| *at >= time).unwrap();
+        let (to_time, to) = self.waypoints[index];
+        let Some(&(from_time, from)) = index.checked_sub(1).map(|index| &self.waypoints[index])
+        else {
+            return Some(to);
+        };
+        let span = (to_time - from_time).as_secs_f64();
+        let fraction = if span > 0.0 { (time - from_time).as_secs_f64() / span } else { 1.0 };
+        let between = |from: u32, to: u32| {
+            (from as f64 + (to as f64 - from as f64) * fraction).round() as u32
+        };
+        Some(Point::new(between(from.x, to.x), between(from.y, to.y)))
+    }
+}
+
+/// Fingers moving together, performed with [`crate::adb::Adb::perform_gesture`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Gesture {
+    pointers: Vec<PointerPath>,
+}
+
+impl Gesture {
+    pub fn new() -> Self {
+        Gesture::default()
+    }
+
+    pub fn with_pointer(mut self, pointer: PointerPath) -> Self {
+        self.pointers.push(pointer);
+        self
+    }
+
+    /// Two fingers level with `center`, moving from `start_spread` to `end_spread` pixels
+    /// apart: a pinch out when the spread grows, a pinch in when it shrinks.
+    pub fn pinch(center: Point, start_spread: u32, end_spread: u32, duration: Duration) -> Self {
+        let finger = |direction: i64| {
+            let at = |spread: u32| {
+                Point::new(offset(center.x, direction * spread as i64 / 2), center.y)
+            };
+            PointerPath::new(at(start_spread)).move_to(at(end_spread), duration)
+        };
+        Gesture::new().with_pointer(finger(-1)).with_pointer(finger(1))
+    }
+
+    /// Two fingers on opposite sides of a circle around `center`, turning `sweep_degrees`
+    /// from `start_degrees`. Angles count clockwise on screen from the right, so a positive
+    /// sweep turns content clockwise.
+    pub fn rotate(
+        center: Point, radius: u32, start_degrees: f64, sweep_degrees: f64, duration: Duration,
+    ) -> Self {
+        let steps = (sweep_degrees.abs() / ROTATION_STEP_DEGREES).ceil().max(1.0) as u32;
+        let finger = |phase: f64| {
+            let at = |degrees: f64| {
+                let radians = (degrees + phase) * PI / 180.0;
+                let x = center.x as f64 + radius as f64 * radians.cos();
+                let y = center.y as f64 + radius as f64 * radians.sin();
+                Point::new(x.round().max(0.0) as u32, y.round().max(0.0) as u32)
+            };
+            let points: Vec<Point> = (1..=steps)
+                .map(|step| at(start_degrees + sweep_degrees * step as f64 / steps as f64))
+                .collect();
+            PointerPath::new(at(start_degrees)).follow(&points, duration)
+        };
+        Gesture::new().with_pointer(finger(0.0)).with_pointer(finger(180.0))
+    }
+
+    /// Two fingers `spacing` pixels apart side by side, dragged from `from` to `to`.
+    pub fn two_finger_scroll(from: Point, to: Point, spacing: u32, duration: Duration) -> Self {
+        let finger = |direction: i64| {
+            let at = |point: Point| {
+                Point::new(offset(point.x, direction * spacing as i64 / 2), point.y)
+            };
+            PointerPath::new(at(from)).move_to(at(to), duration)
+        };
+        Gesture::new().with_pointer(finger(-1)).with_pointer(finger(1))
+    }
+
+    /// One finger following `points` at constant speed.
+    pub fn path(points: &[Point], duration: Duration) -> Self {
+        let Some((first, rest)) = points.split_first() else {
+            return Gesture::new();
+        };
+        Gesture::new().with_pointer(PointerPath::new(*first).follow(rest, duration))
+    }
+
+    pub fn pointers(&self) -> &[PointerPath] {
+        &self.pointers
+    }
+
+    /// Until the last finger lifts.
+    pub fn duration(&self) -> Duration {
+        self.pointers.iter().map(PointerPath::end).max().unwrap_or_default()
+    }
+
+    /// Every pointer's position sampled each `step` and whenever a finger touches down or
+    /// lifts, in time order.
+    pub(crate) fn frames(&self, step: Duration) -> Vec<(Duration, Vec<Option<Point>>)> {
+        let duration = self.duration();
+        let mut times: Vec<Duration> = (0..)
+            .map(|index| step * index)
+            .take_while(|time| *time < duration)
+            .chain(self.pointers.iter().flat_map(|pointer| [pointer.start(), pointer.end()]))
+            .collect();
+        times.sort();
+        times.dedup();
+        times
+            .into_iter()
+            .map(|time| {
+                let positions = self.pointers.iter().map(|pointer| pointer.position_at(time));
+                (time, positions.collect())
+            })
+            .collect()
+    }
+}
+
+fn offset(value: u32, by: i64) -> u32 {
+    (value as i64 + by).max(0) as u32
+}
+
+fn distance(a: Point, b: Point) -> f64 {
+    (a.x as f64 - b.x as f64).hypot(a.y as f64 - b.y as f64)
+}
@@ -1,35 +1,117 @@
 use std::path::PathBuf;
+use std::time::Duration;
 
 use crate::adb::Adb;
 use crate::error::AdbError;
 
 mod process;
+mod runner;
+mod screencap;
+mod server;
+mod sync;
+mod tracker;
 mod adb;
+mod color;
+mod device;
+mod diff;
+mod frames;
+mod geometry;
+mod gesture;
 mod key_code;
+mod perceptual;
+mod template;
 mod tesseract;
 mod utils;
 mod error;
+#[cfg(feature = "tokio")]
+mod async_adb;
 
 pub mod bridge {
     pub use crate::adb::Adb;
     pub use crate::adb::DisplaySize;
+    pub use crate::adb::display::DisplayInfo;
+    pub use crate::adb::display::Insets;
+    pub use crate::adb::display::Rotation;
+    pub use crate::adb::rotation::CoordinateSpace;
+    pub use crate::adb::rotation::ScreenTransform;
+    pub use crate::adb::properties::DeviceInfo;
+    pub use crate::adb::screenrecord::ScreenRecordOptions;
+    pub use crate::adb::screenrecord::ScreenRecording;
+    pub use crate::adb::screenrecord::StoppedRecording;
+    pub use crate::adb::touchscreen::TouchscreenDevice;
+    pub use crate::device::Device;
+    pub use crate::device::DeviceState;
+    pub use crate::device::RebootMode;
+    pub use crate::device::WaitState;
+    #[cfg(feature = "tokio")]
+    pub use crate::async_adb::AsyncAdb;
+    pub use crate::frames::Frame;
+    pub use crate::frames::FrameStream;
+    pub use crate::frames::FrameStreamOptions;
+    pub use crate::gesture::Gesture;
+    pub use crate::gesture::PointerPath;
     pub use crate::key_code::KeyCode;
+    pub use crate::screencap::CaptureMode;
+    pub use crate::screencap::ColorSpace;
+    pub use crate::screencap::RawScreencap;
+    pub use crate::sync::RemoteDirEntry;
+    pub use crate::sync::RemoteStat;
+    pub use crate::sync::SyncProgress;
+    pub use crate::tracker::DeviceEvent;
+    pub use crate::tracker::DeviceTracker;
+
+    pub mod runner {
+        #[cfg(feature = "tokio")]
+        pub use crate::async_adb::AsyncCommandRunner;
+        #[cfg(feature = "tokio")]
+        pub use crate::async_adb::CommandFuture;
+        #[cfg(feature = "tokio")]
+        pub use crate::async_adb::TokioProcessRunner;
+        pub use crate::process::ProcessRunner;
+        pub use crate::runner::CancellationToken;
+        pub use crate::runner::CommandOutput;
+        pub use crate::runner::CommandRunner;
+        pub use crate::runner::RunOptions;
+        pub use crate::runner::ScriptedRunner;
+        pub use crate::server::ServerRunner;
+        pub use crate::server::DEFAULT_SERVER_ADDR;
+    }
 
     pub mod error {
         pub use crate::error::AdbError;
-        pub use crate::error::CaptureScreenError;
-        pub use crate::error::DisplaySizeError;
-        pub use crate::error::ScreenSumError;
     }
 }
 
 pub mod toolkit {
     pub mod image {
+        pub use crate::diff::diff_images;
+        pub use crate::diff::DiffOptions;
+        pub use crate::diff::ImageDiff;
+        pub use crate::template::find_template;
+        pub use crate::template::MatchOptions;
+        pub use crate::template::TemplateMatch;
         pub use crate::tesseract::TesseractImage;
         pub use crate::utils::save_dynamic_image_as_png;
     }
 
+    pub mod color {
+        pub use crate::color::average_color;
+        pub use crate::color::pixel_at;
+        pub use crate::color::Color;
+        pub use crate::color::ColorSignature;
+        pub use crate::color::ColorTolerance;
+        pub use crate::color::Hsv;
+    }
+
+    pub mod geometry {
+        pub use crate::geometry::Point;
+        pub use crate::geometry::Rect;
+    }
+
     pub mod digest {
+        pub use crate::perceptual::perceptual_hash;
+        pub use crate::perceptual::HashAlgorithm;
+        pub use crate::perceptual::PerceptualHash;
         pub use crate::utils::md5_of_bytes;
         pub use crate::utils::md5_of_file;
     }
@@ -67,4 +149,18 @@ impl DarkInstruments {
             target,
         )
     }
+
+    pub fn adb_host(&self) -> Result<Adb, AdbError> {
+        Adb::host(self.args.adb_path.clone())
+    }
+
+    pub fn adb_when_ready(
+        &self, target: Option<String>, timeout: Duration,
+    ) -> Result<Adb, AdbError> {
+        Adb::new_when_ready(
+            self.args.adb_path.clone(),
+            target,
+            timeout,
+        )
+    }
 }
\ No newline at end of file
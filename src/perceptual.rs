@@ -0,0 +1,123 @@
+use std::f64::consts::PI;
+use std::fmt;
+use std::fmt::Display;
+
+use image::imageops::thumbnail;
+use image::{DynamicImage, GrayImage, Luma};
+
+use crate::geometry::Rect;
+
+const HASH_SIDE: u32 = 8;
+const DCT_SIDE: u32 = 32;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum HashAlgorithm {
+    /// aHash: each pixel of an 8x8 thumbnail compared against the mean.
+    Average,
+    /// dHash: horizontal brightness gradients of a 9x8 thumbnail.
+    #[default]
+    Difference,
+    /// pHash: low frequencies of a 32x32 DCT compared against their median, the most robust
+    /// to scaling and compression but the slowest.
+    Perceptual,
+}
+
+/// A 64-bit perceptual hash, where visually similar images differ in few bits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct PerceptualHash {
+    pub algorithm: HashAlgorithm,
+    pub bits: u64,
+}
+
+impl PerceptualHash {
+    /// The number of differing bits, 0 for identical hashes and 64 at most.
+    pub fn distance(&self, other: &PerceptualHash) -> u32 {
+        (self.bits ^ other.bits).count_ones()
+    }
+
+    /// Similarity from 0.0 to 1.0, the share of matching bits.
+    pub fn similarity(&self, other: &PerceptualHash) -> f64 {
+        1.0 - self.distance(other) as f64 / 64.0
+    }
+}
+
+impl Display for PerceptualHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016x}", self.bits)
+    }
+}
+
+/// Hashes `dynamic_image` with `algorithm`, blanking the `exclusions` first so that content
+/// inside them, such as the status bar clock, never affects the hash.
+pub fn perceptual_hash(
+    dynamic_image: &DynamicImage, algorithm: HashAlgorithm, exclusions: &[Rect],
+) -> PerceptualHash {
+    let mut gray = dynamic_image.to_luma8();
+    let bounds = Rect::new(0, 0, gray.width(), gray.height());
+    for exclusion in exclusions.iter().filter_map(|exclusion| exclusion.intersection(&bounds)) {
+        for y in exclusion.y..exclusion.bottom() {
+            for x in exclusion.x..exclusion.right() {
+                gray.put_pixel(x, y, Luma([0]));
+            }
+        }
+    }
+    let bits = match algorithm {
+        HashAlgorithm::Average => average_hash(&gray),
+        HashAlgorithm::Difference => difference_hash(&gray),
+        HashAlgorithm::Perceptual => dct_hash(&gray),
+    };
+    PerceptualHash { algorithm, bits }
+}
+
+fn average_hash(gray: &GrayImage) -> u64 {
+    let small = thumbnail(gray, HASH_SIDE, HASH_SIDE);
+    let values: Vec<f64> = small.pixels().map(|pixel| pixel[0] as f64).collect();
+    let mean = values.iter().sum::<f64>() / values.len() as f64;
+    pack_bits(values.iter().map(|value| *value > mean))
+}
+
+fn difference_hash(gray: &GrayImage) -> u64 {
+    let small = thumbnail(gray, HASH_SIDE + 1, HASH_SIDE);
+    pack_bits((0..HASH_SIDE).flat_map(|y| {
+        let small = &small;
+        (0..HASH_SIDE).map(move |x| small.get_pixel(x, y)[0] < small.get_pixel(x + 1, y)[0])
+    }))
+}
+
+fn dct_hash(gray: &GrayImage) -> u64 {
+    let small = thumbnail(gray, DCT_SIDE, DCT_SIDE);
+    let side = DCT_SIDE as usize;
+    let hash_side = HASH_SIDE as usize;
+    let cosines: Vec<f64> = (0..hash_side)
+        .flat_map(|frequency| {
+            (0..side).map(move |position| {
+                ((2 * position + 1) as f64 * frequency as f64 * PI / (2 * side) as f64).cos()
+            })
+        })
+        .collect();
+    let pixel = |x: usize, y: usize| small.get_pixel(x as u32, y as u32)[0] as f64;
+    // Only the lowest 8x8 frequencies are needed, so the separable DCT is computed directly.
+    let mut rows = vec![0.0; hash_side * side];
+    for v in 0..hash_side {
+        for x in 0..side {
+            rows[v * side + x] = (0..side).map(|y| cosines[v * side + y] * pixel(x, y)).sum();
+        }
+    }
+    let mut coefficients = Vec::with_capacity(hash_side * hash_side);
+    for v in 0..hash_side {
+        for u in 0..hash_side {
+            coefficients.push(
+                (0..side).map(|x| cosines[u * side + x] * rows[v * side + x]).sum::<f64>(),
+            );
+        }
+    }
+    // The DC term only reflects overall brightness and is left out of the median.
+    let mut sorted = coefficients[1..].to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let median = sorted[sorted.len() / 2];
+    pack_bits(coefficients.iter().map(|coefficient| *coefficient > median))
+}
+
+fn pack_bits(bits: impl Iterator<Item = bool>) -> u64 {
+    bits.fold(0, |packed, bit| (packed << 1) | bit as u64)
+}
@@ -1,56 +1,87 @@
-use std::process::{Command, Stdio};
+use std::io;
+use std::io::Read;
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
 
-pub(crate) struct Process;
+use tracing::trace;
 
-impl Process {
-    pub(crate) fn exec(program: &str, args: &[&str]) -> Option<(String, String)> {
-        Self::exec_command_and_capture_output_as_strings(program, args)
-    }
+use crate::runner::{CommandOutput, CommandRunner, RunOptions};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
 
-    pub(crate) fn exec_and_return_stdout_bytes(program: &str, args: &[&str]) -> Option<Vec<u8>> {
-        Self::exec_command_and_return_stdout_bytes(program, args)
+/// The default [`CommandRunner`], spawning the adb binary for every invocation.
+#[derive(Clone, Debug)]
+pub struct ProcessRunner {
+    program: PathBuf,
+}
+
+impl ProcessRunner {
+    pub fn new(program: PathBuf) -> Self {
+        ProcessRunner { program }
     }
 
-    fn exec_command_and_return_stdout_bytes(
-        program: &str, args: &[&str],
-    ) -> Option<Vec<u8>> {
-        Self::exec_command_and_capture_output(
-            program, args,
-        ).map(|output| output.0)
+    pub fn program(&self) -> &PathBuf {
+        &self.program
     }
 
-    fn exec_command_and_capture_output_as_strings(
-        program: &str, args: &[&str],
-    ) -> Option<(String, String)> {
-        Self::exec_command_and_capture_output(
-            program, args,
-        ).map(|(stdout, stderr)| {
-            (
-                String::from_utf8_lossy(&stdout).trim().to_string(),
-                String::from_utf8_lossy(&stderr).trim().to_string(),
-            )
+    fn drain<R: Read + Send + 'static>(pipe: Option<R>) -> JoinHandle<Vec<u8>> {
+        thread::spawn(move || {
+            let mut buffer = Vec::new();
+            if let Some(mut pipe) = pipe {
+                let _ = pipe.read_to_end(&mut buffer);
+            }
+            buffer
         })
     }
 
-    fn exec_command_and_capture_output(
-        program: &str, args: &[&str],
-    ) -> Option<(Vec<u8>, Vec<u8>)> {
-        println!(
-            "EXEC: program='{}', arguments='{:#?}'",
-            program,
-            args.iter().map(|&x| x.to_string()).collect::<Vec<String>>().join(" ")
-        );
-        let output = Command::new(program)
+    fn kill(child: &mut Child) {
+        let _ = child.kill();
+        let _ = child.wait();
+    }
+}
+
+impl CommandRunner for ProcessRunner {
+    fn run(&self, args: &[&str], options: &RunOptions) -> io::Result<CommandOutput> {
+        trace!(program = %self.program.display(), "spawning process");
+        let started = Instant::now();
+        let mut child = Command::new(&self.program)
             .args(args)
             .stdout(Stdio::piped())
-            .spawn()
-            .and_then(|process| process.wait_with_output())
-            .map(|output| (output.stdout, output.stderr))
-            .unwrap_or_else(|err| {
-                eprintln!("Error executing command: {}", err);
-                (Vec::new(), Vec::new())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        if options.timeout.is_none() && options.cancellation.is_none() {
+            let output = child.wait_with_output()?;
+            return Ok(CommandOutput {
+                stdout: output.stdout,
+                stderr: output.stderr,
+                exit_code: output.status.code(),
+                duration: started.elapsed(),
             });
-
-        Some(output)
+        }
+        let stdout = Self::drain(child.stdout.take());
+        let stderr = Self::drain(child.stderr.take());
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
+            if options.is_cancelled() {
+                Self::kill(&mut child);
+                return Err(io::Error::new(io::ErrorKind::Interrupted, "command cancelled"));
+            }
+            if options.timeout.is_some_and(|timeout| started.elapsed() >= timeout) {
+                Self::kill(&mut child);
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "command timed out"));
+            }
+            thread::sleep(POLL_INTERVAL);
+        };
+        Ok(CommandOutput {
+            stdout: stdout.join().unwrap_or_default(),
+            stderr: stderr.join().unwrap_or_default(),
+            exit_code: status.code(),
+            duration: started.elapsed(),
+        })
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,156 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Debug;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+/// The result of a completed adb invocation.
+///
+/// `exit_code` is `None` when the process was terminated by a signal.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CommandOutput {
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+    pub exit_code: Option<i32>,
+    pub duration: Duration,
+}
+
+impl CommandOutput {
+    pub fn new(stdout: Vec<u8>, stderr: Vec<u8>, exit_code: Option<i32>) -> Self {
+        CommandOutput { stdout, stderr, exit_code, duration: Duration::ZERO }
+    }
+
+    pub fn success(&self) -> bool {
+        self.exit_code == Some(0)
+    }
+
+    pub fn from_stdout(stdout: &str) -> Self {
+        CommandOutput::new(stdout.as_bytes().to_vec(), Vec::new(), Some(0))
+    }
+
+    pub fn stdout_string(&self) -> String {
+        String::from_utf8_lossy(&self.stdout).trim().to_string()
+    }
+
+    pub fn stderr_string(&self) -> String {
+        String::from_utf8_lossy(&self.stderr).trim().to_string()
+    }
+}
+
+/// A handle that lets another thread abort in-flight commands.
+///
+/// Clones share the same state, so cancelling any clone cancels them all.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        CancellationToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct RunOptions {
+    pub timeout: Option<Duration>,
+    pub cancellation: Option<CancellationToken>,
+}
+
+impl RunOptions {
+    pub fn is_cancelled(&self) -> bool {
+        self.cancellation
+            .as_ref()
+            .is_some_and(|cancellation| cancellation.is_cancelled())
+    }
+}
+
+/// Executes adb invocations on behalf of [`Adb`](crate::adb::Adb).
+///
+/// `args` is the full argument vector passed to adb, including `-s <serial>` when the
+/// `Adb` instance has a target. Implementations report an expired `options.timeout` as
+/// [`io::ErrorKind::TimedOut`] and a cancelled `options.cancellation` as
+/// [`io::ErrorKind::Interrupted`].
+pub trait CommandRunner: Debug + Send + Sync {
+    fn run(&self, args: &[&str], options: &RunOptions) -> io::Result<CommandOutput>;
+}
+
+/// A [`CommandRunner`] returning canned outputs per argument vector, for testing scripts
+/// without a device attached.
+///
+/// Outputs registered for the same arguments are returned in order, the last one repeating
+/// until another output is registered. Unscripted invocations return an empty successful
+/// output. Every invocation is recorded and available through [`ScriptedRunner::calls`].
+#[derive(Debug, Default)]
+pub struct ScriptedRunner {
+    responses: Mutex<HashMap<Vec<String>, Script>>,
+    calls: Mutex<Vec<Vec<String>>>,
+}
+
+#[derive(Debug, Default)]
+struct Script {
+    queue: VecDeque<CommandOutput>,
+    repeating: bool,
+}
+
+impl ScriptedRunner {
+    pub fn new() -> Self {
+        ScriptedRunner::default()
+    }
+
+    pub fn respond(&self, args: &[&str], output: CommandOutput) -> &Self {
+        let mut responses = self.responses.lock().unwrap();
+        let script = responses.entry(Self::key(args)).or_default();
+        if script.repeating {
+            script.queue.clear();
+            script.repeating = false;
+        }
+        script.queue.push_back(output);
+        self
+    }
+
+    pub fn respond_stdout(&self, args: &[&str], stdout: &str) -> &Self {
+        self.respond(args, CommandOutput::from_stdout(stdout))
+    }
+
+    pub fn calls(&self) -> Vec<Vec<String>> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    pub fn clear_calls(&self) {
+        self.calls.lock().unwrap().clear();
+    }
+
+    fn key(args: &[&str]) -> Vec<String> {
+        args.iter().map(|&arg| arg.to_string()).collect()
+    }
+}
+
+impl CommandRunner for ScriptedRunner {
+    fn run(&self, args: &[&str], options: &RunOptions) -> io::Result<CommandOutput> {
+        let key = Self::key(args);
+        self.calls.lock().unwrap().push(key.clone());
+        if options.is_cancelled() {
+            return Err(io::Error::new(io::ErrorKind::Interrupted, "command cancelled"));
+        }
+        let mut responses = self.responses.lock().unwrap();
+        let output = match responses.get_mut(&key) {
+            Some(script) if script.queue.len() > 1 => script.queue.pop_front().unwrap(),
+            Some(script) => {
+                script.repeating = true;
+                script.queue.front().cloned().unwrap_or_default()
+            }
+            None => CommandOutput::new(Vec::new(), Vec::new(), Some(0)),
+        };
+        Ok(output)
+    }
+}
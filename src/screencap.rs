@@ -0,0 +1,112 @@
+use image::RgbaImage;
+
+use crate::error::AdbError;
+
+const FORMAT_RGBA_8888: u32 = 1;
+const FORMAT_RGBX_8888: u32 = 2;
+const FORMAT_RGB_565: u32 = 4;
+const COLOR_SPACE_SRGB: u32 = 1;
+const COLOR_SPACE_DISPLAY_P3: u32 = 2;
+
+/// How screenshots are transferred from the device.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CaptureMode {
+    /// `screencap -p`: PNG encoded on the device, small but slow to encode and decode.
+    #[default]
+    Png,
+    /// Plain `screencap`: the raw framebuffer, several megabytes per frame but no encoding.
+    Raw,
+}
+
+impl CaptureMode {
+    pub(crate) fn args(&self) -> &'static [&'static str] {
+        match self {
+            CaptureMode::Png => &["exec-out", "screencap", "-p"],
+            CaptureMode::Raw => &["exec-out", "screencap"],
+        }
+    }
+}
+
+/// The colorspace a raw `screencap` frame's pixel values are in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ColorSpace {
+    /// Not reported, as before Android 9, or not recognized.
+    #[default]
+    Unknown,
+    Srgb,
+    /// Wide gamut, so the same pixel values are more saturated than in sRGB.
+    DisplayP3,
+}
+
+/// A raw `screencap` frame, see [`crate::adb::Adb::capture_raw_screencap`].
+#[derive(Clone, Debug)]
+pub struct RawScreencap {
+    pub image: RgbaImage,
+    pub color_space: ColorSpace,
+}
+
+/// Converts raw `screencap` output into an image.
+///
+/// The output starts with little-endian `u32` width, height and pixel format, followed since
+/// Android 9 by a colorspace word, which is told apart by the size of the pixel data.
+pub(crate) fn parse_raw_screencap(bytes: &[u8]) -> Result<RawScreencap, AdbError> {
+    let word = |index: usize| {
+        bytes
+            .get(index * 4..index * 4 + 4)
+            .map(|word| u32::from_le_bytes(word.try_into().unwrap()))
+    };
+    let (Some(width), Some(height), Some(format)) = (word(0), word(1), word(2)) else {
+        return Err(AdbError::UnparseableOutput("truncated screencap header".to_string()));
+    };
+    let bytes_per_pixel = match format {
+        FORMAT_RGBA_8888 | FORMAT_RGBX_8888 => 4,
+        FORMAT_RGB_565 => 2,
+        other => {
+            return Err(AdbError::UnparseableOutput(format!(
+                "unsupported screencap pixel format {}",
+                other,
+            )));
+        }
+    };
+    let pixel_bytes = width as usize * height as usize * bytes_per_pixel;
+    let (header, pixels) = [16, 12]
+        .into_iter()
+        .filter_map(|header| Some((header, bytes.get(header..)?)))
+        .find(|(_, pixels)| pixels.len() == pixel_bytes)
+        .ok_or_else(|| AdbError::UnparseableOutput(format!(
+            "screencap returned {} bytes for a {}x{} frame",
+            bytes.len(),
+            width,
+            height,
+        )))?;
+    let rgba = match format {
+        FORMAT_RGBA_8888 => pixels.to_vec(),
+        FORMAT_RGBX_8888 => pixels
+            .chunks_exact(4)
+            .flat_map(|pixel| [pixel[0], pixel[1], pixel[2], u8::MAX])
+            .collect(),
+        _ => pixels
+            .chunks_exact(2)
+            .flat_map(|pixel| {
+                let value = u16::from_le_bytes([pixel[0], pixel[1]]);
+                let red = (value >> 11) & 0x1f;
+                let green = (value >> 5) & 0x3f;
+                let blue = value & 0x1f;
+                [
+                    (red * 255 / 31) as u8,
+                    (green * 255 / 63) as u8,
+                    (blue * 255 / 31) as u8,
+                    u8::MAX,
+                ]
+            })
+            .collect(),
+    };
+    let color_space = match (header, word(3)) {
+        (16, Some(COLOR_SPACE_SRGB)) => ColorSpace::Srgb,
+        (16, Some(COLOR_SPACE_DISPLAY_P3)) => ColorSpace::DisplayP3,
+        _ => ColorSpace::Unknown,
+    };
+    let image = RgbaImage::from_raw(width, height, rgba)
+        .ok_or_else(|| AdbError::UnparseableOutput("invalid screencap frame".to_string()))?;
+    Ok(RawScreencap { image, color_space })
+}
@@ -0,0 +1,338 @@
+use std::error::Error;
+use std::fmt;
+use std::fmt::Display;
+use std::io;
+use std::io::{Read, Write};
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, TcpStream};
+use std::time::{Duration, Instant};
+
+use tracing::trace;
+
+use crate::runner::{CommandOutput, CommandRunner, RunOptions};
+
+pub const DEFAULT_SERVER_ADDR: SocketAddr =
+    SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 5037));
+
+const SERVER_PORT_ENV: &str = "ANDROID_ADB_SERVER_PORT";
+const READ_SLICE: Duration = Duration::from_millis(50);
+const SHELL_V2_STDOUT: u8 = 1;
+const SHELL_V2_STDERR: u8 = 2;
+const SHELL_V2_EXIT: u8 = 3;
+
+/// The adb server address, honouring `ANDROID_ADB_SERVER_PORT` like the adb binary does.
+pub(crate) fn default_server_addr() -> SocketAddr {
+    std::env::var(SERVER_PORT_ENV)
+        .ok()
+        .and_then(|port| port.parse::<u16>().ok())
+        .map_or(DEFAULT_SERVER_ADDR, |port| {
+            SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, port))
+        })
+}
+
+/// A `FAIL` reply from the adb server, carrying its message.
+#[derive(Debug, Clone)]
+pub(crate) struct ServerFailure(pub(crate) String);
+
+impl Display for ServerFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Error for ServerFailure {}
+
+pub(crate) fn server_failure(err: &io::Error) -> Option<&ServerFailure> {
+    err.get_ref().and_then(|inner| inner.downcast_ref::<ServerFailure>())
+}
+
+/// A single smart-socket connection to the adb server.
+///
+/// Reads honour the deadline and cancellation of the [`RunOptions`] the connection was
+/// opened with, failing with [`io::ErrorKind::TimedOut`] or [`io::ErrorKind::Interrupted`].
+pub(crate) struct ServerConnection {
+    stream: TcpStream,
+    options: RunOptions,
+    deadline: Option<Instant>,
+}
+
+impl ServerConnection {
+    pub(crate) fn connect(addr: SocketAddr, options: &RunOptions) -> io::Result<Self> {
+        let stream = match options.timeout {
+            Some(timeout) => TcpStream::connect_timeout(&addr, timeout)?,
+            None => TcpStream::connect(addr)?,
+        };
+        stream.set_nodelay(true)?;
+        Ok(ServerConnection {
+            stream,
+            options: options.clone(),
+            deadline: options.timeout.map(|timeout| Instant::now() + timeout),
+        })
+    }
+
+    /// Sends a length-prefixed service request and waits for `OKAY`.
+    pub(crate) fn request(&mut self, service: &str) -> io::Result<()> {
+        trace!(service, "adb server request");
+        self.write_all(format!("{:04x}{}", service.len(), service).as_bytes())?;
+        self.read_status()
+    }
+
+    pub(crate) fn read_status(&mut self) -> io::Result<()> {
+        let status = self.read_exact_bytes(4)?;
+        match &status[..] {
+            b"OKAY" => Ok(()),
+            b"FAIL" => {
+                let message = self.read_length_prefixed()?;
+                Err(io::Error::other(ServerFailure(message)))
+            }
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unexpected adb server status {:?}", String::from_utf8_lossy(&status)),
+            )),
+        }
+    }
+
+    pub(crate) fn read_length_prefixed(&mut self) -> io::Result<String> {
+        let length = self.read_exact_bytes(4)?;
+        let length = usize::from_str_radix(&String::from_utf8_lossy(&length), 16)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        let payload = self.read_exact_bytes(length)?;
+        Ok(String::from_utf8_lossy(&payload).to_string())
+    }
+
+    pub(crate) fn write_all(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.stream.set_write_timeout(self.remaining()?)?;
+        self.stream.write_all(bytes).map_err(Self::timed_out)
+    }
+
+    /// Reads up to `buffer.len()` bytes, returning 0 at end of stream.
+    pub(crate) fn read_chunk(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if self.options.is_cancelled() {
+                return Err(io::Error::new(io::ErrorKind::Interrupted, "command cancelled"));
+            }
+            let slice = match self.remaining()? {
+                Some(remaining) => remaining.min(READ_SLICE),
+                None => READ_SLICE,
+            };
+            self.stream.set_read_timeout(Some(slice))?;
+            match self.stream.read(buffer) {
+                Ok(read) => return Ok(read),
+                Err(err) if matches!(
+                    err.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut | io::ErrorKind::Interrupted
+                ) => continue,
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    pub(crate) fn read_exact_bytes(&mut self, length: usize) -> io::Result<Vec<u8>> {
+        let mut buffer = vec![0; length];
+        let mut filled = 0;
+        while filled < length {
+            match self.read_chunk(&mut buffer[filled..])? {
+                0 => return Err(io::Error::from(io::ErrorKind::UnexpectedEof)),
+                read => filled += read,
+            }
+        }
+        Ok(buffer)
+    }
+
+    pub(crate) fn read_to_end(&mut self) -> io::Result<Vec<u8>> {
+        let mut output = Vec::new();
+        let mut buffer = [0; 64 * 1024];
+        loop {
+            match self.read_chunk(&mut buffer)? {
+                0 => return Ok(output),
+                read => output.extend_from_slice(&buffer[..read]),
+            }
+        }
+    }
+
+    fn remaining(&self) -> io::Result<Option<Duration>> {
+        match self.deadline {
+            Some(deadline) => {
+                let now = Instant::now();
+                if now >= deadline {
+                    return Err(io::Error::new(io::ErrorKind::TimedOut, "command timed out"));
+                }
+                Ok(Some(deadline - now))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn timed_out(err: io::Error) -> io::Error {
+        match err.kind() {
+            io::ErrorKind::WouldBlock => {
+                io::Error::new(io::ErrorKind::TimedOut, "command timed out")
+            }
+            _ => err,
+        }
+    }
+}
+
+/// A [`CommandRunner`] speaking the adb server smart-socket protocol directly over TCP,
+/// so commands run without spawning the adb binary.
+///
+/// Supports `devices [-l]`, `start-server`, `kill-server`, `connect`, `disconnect`, `pair`,
+/// `tcpip`, `reboot`, `shell` and `exec-out`, with an optional leading `-s <serial>`. `shell`
+/// uses the shell v2 protocol to report exit codes, falling back to the legacy protocol on
+/// devices without it. Server `FAIL` replies are reported the way the adb binary does, as
+/// `error: <message>` on stderr with exit code 1.
+#[derive(Clone, Debug)]
+pub struct ServerRunner {
+    addr: SocketAddr,
+}
+
+impl ServerRunner {
+    pub fn new(addr: SocketAddr) -> Self {
+        ServerRunner { addr }
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    fn connect(&self, options: &RunOptions) -> io::Result<ServerConnection> {
+        ServerConnection::connect(self.addr, options)
+    }
+
+    fn transport(
+        &self, serial: Option<&str>, options: &RunOptions,
+    ) -> io::Result<ServerConnection> {
+        let mut connection = self.connect(options)?;
+        match serial {
+            Some(serial) => connection.request(&format!("host:transport:{}", serial))?,
+            None => connection.request("host:transport-any")?,
+        }
+        Ok(connection)
+    }
+
+    fn host_query(&self, service: &str, options: &RunOptions) -> io::Result<Vec<u8>> {
+        let mut connection = self.connect(options)?;
+        connection.request(service)?;
+        Ok(connection.read_length_prefixed()?.into_bytes())
+    }
+
+    /// Runs a host service whose reply is a message the adb binary would print verbatim.
+    fn host_reply(&self, service: &str, options: &RunOptions) -> io::Result<CommandOutput> {
+        let stdout = self.host_query(service, options)?;
+        Ok(CommandOutput::new(stdout, Vec::new(), Some(0)))
+    }
+
+    fn devices(&self, long: bool, options: &RunOptions) -> io::Result<CommandOutput> {
+        let service = if long { "host:devices-l" } else { "host:devices" };
+        let mut stdout = b"List of devices attached\n".to_vec();
+        stdout.extend(self.host_query(service, options)?);
+        Ok(CommandOutput::new(stdout, Vec::new(), Some(0)))
+    }
+
+    fn shell(
+        &self, serial: Option<&str>, command: &str, options: &RunOptions,
+    ) -> io::Result<CommandOutput> {
+        let mut connection = self.transport(serial, options)?;
+        match connection.request(&format!("shell,v2,raw:{}", command)) {
+            Ok(()) => Self::read_shell_v2(&mut connection),
+            Err(err) if server_failure(&err).is_some() => {
+                let mut connection = self.transport(serial, options)?;
+                connection.request(&format!("shell:{}", command))?;
+                Ok(CommandOutput::new(connection.read_to_end()?, Vec::new(), Some(0)))
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    fn read_shell_v2(connection: &mut ServerConnection) -> io::Result<CommandOutput> {
+        let mut output = CommandOutput::new(Vec::new(), Vec::new(), None);
+        loop {
+            let mut header = [0; 5];
+            match connection.read_chunk(&mut header[..1])? {
+                0 => return Ok(output),
+                _ => header[1..].copy_from_slice(&connection.read_exact_bytes(4)?),
+            }
+            let length = u32::from_le_bytes([header[1], header[2], header[3], header[4]]);
+            let payload = connection.read_exact_bytes(length as usize)?;
+            match header[0] {
+                SHELL_V2_STDOUT => output.stdout.extend(payload),
+                SHELL_V2_STDERR => output.stderr.extend(payload),
+                SHELL_V2_EXIT => {
+                    output.exit_code = payload.first().map(|&code| code as i32);
+                    return Ok(output);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn exec_out(
+        &self, serial: Option<&str>, command: &str, options: &RunOptions,
+    ) -> io::Result<CommandOutput> {
+        let mut connection = self.transport(serial, options)?;
+        connection.request(&format!("exec:{}", command))?;
+        Ok(CommandOutput::new(connection.read_to_end()?, Vec::new(), Some(0)))
+    }
+
+    fn dispatch(
+        &self, serial: Option<&str>, args: &[&str], options: &RunOptions,
+    ) -> io::Result<CommandOutput> {
+        match args {
+            ["devices"] => self.devices(false, options),
+            ["devices", "-l"] => self.devices(true, options),
+            ["start-server"] => {
+                self.host_query("host:version", options)?;
+                Ok(CommandOutput::new(Vec::new(), Vec::new(), Some(0)))
+            }
+            ["kill-server"] => {
+                let mut connection = self.connect(options)?;
+                connection.request("host:kill")?;
+                Ok(CommandOutput::new(Vec::new(), Vec::new(), Some(0)))
+            }
+            ["connect", serial] => self.host_reply(&format!("host:connect:{}", serial), options),
+            ["disconnect"] => self.host_reply("host:disconnect:", options),
+            ["disconnect", serial] => {
+                self.host_reply(&format!("host:disconnect:{}", serial), options)
+            }
+            ["pair", serial, pairing_code] => {
+                self.host_reply(&format!("host:pair:{}:{}", pairing_code, serial), options)
+            }
+            ["tcpip", port] => {
+                let mut connection = self.transport(serial, options)?;
+                connection.request(&format!("tcpip:{}", port))?;
+                Ok(CommandOutput::new(connection.read_to_end()?, Vec::new(), Some(0)))
+            }
+            ["reboot"] | ["reboot", _] => {
+                let mut connection = self.transport(serial, options)?;
+                connection.request(&format!("reboot:{}", args.get(1).unwrap_or(&"")))?;
+                Ok(CommandOutput::new(connection.read_to_end()?, Vec::new(), Some(0)))
+            }
+            ["shell", command @ ..] => self.shell(serial, &command.join(" "), options),
+            ["exec-out", command @ ..] => self.exec_out(serial, &command.join(" "), options),
+            _ => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("'{}' is not supported by the adb server transport", args.join(" ")),
+            )),
+        }
+    }
+}
+
+impl CommandRunner for ServerRunner {
+    fn run(&self, args: &[&str], options: &RunOptions) -> io::Result<CommandOutput> {
+        let started = Instant::now();
+        let (serial, args) = match args {
+            ["-s", serial, rest @ ..] => (Some(*serial), rest),
+            _ => (None, args),
+        };
+        let mut output = match self.dispatch(serial, args, options) {
+            Ok(output) => output,
+            Err(err) => match server_failure(&err) {
+                Some(failure) => CommandOutput::new(
+                    Vec::new(), format!("error: {}", failure).into_bytes(), Some(1),
+                ),
+                None => return Err(err),
+            },
+        };
+        output.duration = started.elapsed();
+        Ok(output)
+    }
+}
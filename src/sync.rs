@@ -0,0 +1,346 @@
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{Read, Write};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::runner::RunOptions;
+use crate::server::{ServerConnection, ServerFailure};
+
+const SYNC_DATA_MAX: usize = 64 * 1024;
+const S_IFMT: u32 = 0o170000;
+const S_IFDIR: u32 = 0o040000;
+const S_IFREG: u32 = 0o100000;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RemoteStat {
+    pub mode: u32,
+    pub size: u32,
+    pub mtime: SystemTime,
+}
+
+impl RemoteStat {
+    pub fn exists(&self) -> bool {
+        self.mode != 0
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.mode & S_IFMT == S_IFDIR
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.mode & S_IFMT == S_IFREG
+    }
+
+    pub fn permissions(&self) -> u32 {
+        self.mode & 0o7777
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RemoteDirEntry {
+    pub name: String,
+    pub stat: RemoteStat,
+}
+
+/// Progress of a single file transfer; `path` is the remote path of the file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SyncProgress {
+    pub path: String,
+    pub transferred: u64,
+    pub total: u64,
+}
+
+/// A connection in sync mode, speaking the `STAT`/`LIST`/`RECV`/`SEND` protocol.
+pub(crate) struct SyncConnection {
+    connection: ServerConnection,
+}
+
+impl SyncConnection {
+    pub(crate) fn open(
+        addr: SocketAddr, serial: Option<&str>, options: &RunOptions,
+    ) -> io::Result<Self> {
+        let mut connection = ServerConnection::connect(addr, options)?;
+        match serial {
+            Some(serial) => connection.request(&format!("host:transport:{}", serial))?,
+            None => connection.request("host:transport-any")?,
+        }
+        connection.request("sync:")?;
+        Ok(SyncConnection { connection })
+    }
+
+    fn send_header(&mut self, id: &[u8; 4], length: u32) -> io::Result<()> {
+        self.connection.write_all(&[&id[..], &length.to_le_bytes()].concat())
+    }
+
+    fn send_request(&mut self, id: &[u8; 4], path: &str) -> io::Result<()> {
+        self.send_header(id, path.len() as u32)?;
+        self.connection.write_all(path.as_bytes())
+    }
+
+    fn read_u32(&mut self) -> io::Result<u32> {
+        let bytes = self.connection.read_exact_bytes(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn read_id(&mut self) -> io::Result<[u8; 4]> {
+        let bytes = self.connection.read_exact_bytes(4)?;
+        Ok([bytes[0], bytes[1], bytes[2], bytes[3]])
+    }
+
+    fn read_failure(&mut self) -> io::Error {
+        let message = self.read_u32()
+            .and_then(|length| self.connection.read_exact_bytes(length as usize));
+        match message {
+            Ok(message) => io::Error::other(
+                ServerFailure(String::from_utf8_lossy(&message).to_string()),
+            ),
+            Err(err) => err,
+        }
+    }
+
+    fn unexpected(id: [u8; 4]) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unexpected sync response {:?}", String::from_utf8_lossy(&id)),
+        )
+    }
+
+    fn read_stat_fields(&mut self) -> io::Result<RemoteStat> {
+        let mode = self.read_u32()?;
+        let size = self.read_u32()?;
+        let mtime = self.read_u32()?;
+        Ok(RemoteStat {
+            mode,
+            size,
+            mtime: UNIX_EPOCH + Duration::from_secs(mtime as u64),
+        })
+    }
+
+    pub(crate) fn stat(&mut self, path: &str) -> io::Result<RemoteStat> {
+        self.send_request(b"STAT", path)?;
+        match &self.read_id()? {
+            b"STAT" => self.read_stat_fields(),
+            b"FAIL" => Err(self.read_failure()),
+            id => Err(Self::unexpected(*id)),
+        }
+    }
+
+    pub(crate) fn list(&mut self, path: &str) -> io::Result<Vec<RemoteDirEntry>> {
+        self.send_request(b"LIST", path)?;
+        let mut entries = Vec::new();
+        loop {
+            match &self.read_id()? {
+                b"DENT" => {
+                    let stat = self.read_stat_fields()?;
+                    let length = self.read_u32()?;
+                    let name = self.connection.read_exact_bytes(length as usize)?;
+                    let name = String::from_utf8_lossy(&name).to_string();
+                    if name != "." && name != ".." {
+                        entries.push(RemoteDirEntry { name, stat });
+                    }
+                }
+                b"DONE" => {
+                    self.connection.read_exact_bytes(16)?;
+                    return Ok(entries);
+                }
+                b"FAIL" => return Err(self.read_failure()),
+                id => return Err(Self::unexpected(*id)),
+            }
+        }
+    }
+
+    pub(crate) fn recv(
+        &mut self, path: &str, writer: &mut dyn Write, progress: &mut dyn FnMut(u64),
+    ) -> io::Result<()> {
+        self.send_request(b"RECV", path)?;
+        let mut transferred = 0;
+        loop {
+            match &self.read_id()? {
+                b"DATA" => {
+                    let length = self.read_u32()?;
+                    let chunk = self.connection.read_exact_bytes(length as usize)?;
+                    writer.write_all(&chunk)?;
+                    transferred += chunk.len() as u64;
+                    progress(transferred);
+                }
+                b"DONE" => {
+                    self.read_u32()?;
+                    return Ok(());
+                }
+                b"FAIL" => return Err(self.read_failure()),
+                id => return Err(Self::unexpected(*id)),
+            }
+        }
+    }
+
+    pub(crate) fn send(
+        &mut self,
+        path: &str, mode: u32, mtime: u32,
+        reader: &mut dyn Read, progress: &mut dyn FnMut(u64),
+    ) -> io::Result<()> {
+        self.send_request(b"SEND", &format!("{},{}", path, mode))?;
+        let mut buffer = vec![0; SYNC_DATA_MAX];
+        let mut transferred = 0;
+        loop {
+            let read = reader.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            self.send_header(b"DATA", read as u32)?;
+            self.connection.write_all(&buffer[..read])?;
+            transferred += read as u64;
+            progress(transferred);
+        }
+        self.send_header(b"DONE", mtime)?;
+        match &self.read_id()? {
+            b"OKAY" => {
+                self.read_u32()?;
+                Ok(())
+            }
+            b"FAIL" => Err(self.read_failure()),
+            id => Err(Self::unexpected(*id)),
+        }
+    }
+
+    /// Pushes a file or a directory tree, preserving mode and modification time, and returns
+    /// the remote paths of empty directories. The sync protocol only creates directories that
+    /// files are sent into, so those are left for the caller to create.
+    ///
+    /// Like `adb push`, pushing onto an existing remote directory places the source inside it.
+    pub(crate) fn push(
+        &mut self, local: &Path, remote: &str, progress: &mut dyn FnMut(&SyncProgress),
+    ) -> io::Result<Vec<String>> {
+        let remote_stat = self.stat(remote)?;
+        let remote = match (remote_stat.is_dir(), local.file_name()) {
+            (true, Some(file_name)) => join_remote(remote, &file_name.to_string_lossy()),
+            _ => remote.to_string(),
+        };
+        let mut empty_dirs = Vec::new();
+        self.push_entry(local, &remote, &mut empty_dirs, progress)?;
+        Ok(empty_dirs)
+    }
+
+    fn push_entry(
+        &mut self,
+        local: &Path, remote: &str, empty_dirs: &mut Vec<String>,
+        progress: &mut dyn FnMut(&SyncProgress),
+    ) -> io::Result<()> {
+        let metadata = fs::metadata(local)?;
+        if metadata.is_dir() {
+            let entries = fs::read_dir(local)?.collect::<io::Result<Vec<_>>>()?;
+            if entries.is_empty() {
+                empty_dirs.push(remote.to_string());
+            }
+            for entry in entries {
+                let remote_child = join_remote(remote, &entry.file_name().to_string_lossy());
+                self.push_entry(&entry.path(), &remote_child, empty_dirs, progress)?;
+            }
+            return Ok(());
+        }
+        let mtime = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |since_epoch| since_epoch.as_secs() as u32);
+        let total = metadata.len();
+        let mut file = File::open(local)?;
+        self.send(
+            remote, local_mode(&metadata), mtime, &mut file,
+            &mut |transferred| progress(&SyncProgress {
+                path: remote.to_string(),
+                transferred,
+                total,
+            }),
+        )
+    }
+
+    /// Pulls a file or a directory tree, preserving modification time and, on unix, mode.
+    ///
+    /// Like `adb pull`, pulling into an existing local directory places the source inside it.
+    pub(crate) fn pull(
+        &mut self, remote: &str, local: &Path, progress: &mut dyn FnMut(&SyncProgress),
+    ) -> io::Result<()> {
+        let stat = self.stat(remote)?;
+        if !stat.exists() {
+            return Err(io::Error::other(ServerFailure(
+                format!("remote object '{}' does not exist", remote),
+            )));
+        }
+        let local = match (local.is_dir(), remote.trim_end_matches('/').rsplit('/').next()) {
+            (true, Some(name)) if !name.is_empty() => local.join(name),
+            _ => local.to_path_buf(),
+        };
+        self.pull_entry(remote, &stat, &local, progress)
+    }
+
+    fn pull_entry(
+        &mut self,
+        remote: &str, stat: &RemoteStat, local: &Path,
+        progress: &mut dyn FnMut(&SyncProgress),
+    ) -> io::Result<()> {
+        if stat.is_dir() {
+            fs::create_dir_all(local)?;
+            for entry in self.list(remote)? {
+                let remote_child = join_remote(remote, &entry.name);
+                self.pull_entry(&remote_child, &entry.stat, &local.join(&entry.name), progress)?;
+            }
+            return Ok(());
+        }
+        let total = stat.size as u64;
+        let mut file = File::create(local)?;
+        let received = self
+            .recv(remote, &mut file, &mut |transferred| progress(&SyncProgress {
+                path: remote.to_string(),
+                transferred,
+                total,
+            }))
+            .and_then(|()| file.set_modified(stat.mtime));
+        if let Err(err) = received {
+            // Not leaving a truncated file behind that looks like a complete pull.
+            drop(file);
+            let _ = fs::remove_file(local);
+            return Err(err);
+        }
+        set_local_permissions(local, stat.permissions())
+    }
+}
+
+impl Drop for SyncConnection {
+    fn drop(&mut self) {
+        let _ = self.send_header(b"QUIT", 0);
+    }
+}
+
+fn join_remote(parent: &str, name: &str) -> String {
+    format!("{}/{}", parent.trim_end_matches('/'), name)
+}
+
+#[cfg(unix)]
+fn local_mode(metadata: &fs::Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+
+    S_IFREG | (metadata.permissions().mode() & 0o7777)
+}
+
+#[cfg(not(unix))]
+fn local_mode(_metadata: &fs::Metadata) -> u32 {
+    S_IFREG | 0o644
+}
+
+#[cfg(unix)]
+fn set_local_permissions(local: &Path, permissions: u32) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    if permissions == 0 {
+        return Ok(());
+    }
+    fs::set_permissions(local, fs::Permissions::from_mode(permissions))
+}
+
+#[cfg(not(unix))]
+fn set_local_permissions(_local: &Path, _permissions: u32) -> io::Result<()> {
+    Ok(())
+}
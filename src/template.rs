@@ -0,0 +1,256 @@
+use image::imageops::{resize, FilterType};
+use image::{DynamicImage, GrayImage};
+
+use crate::geometry::Rect;
+
+/// Templates are searched on a downscaled copy whose template side is about this long,
+/// then refined at full resolution.
+const COARSE_TEMPLATE_SIDE: u32 = 16;
+/// How far below the threshold a coarse score may be and still be refined, since
+/// downscaling blurs away detail and lowers scores.
+const COARSE_MARGIN: f64 = 0.25;
+const MIN_TEMPLATE_SIDE: u32 = 4;
+
+#[derive(Clone, Debug)]
+pub struct MatchOptions {
+    /// Minimum normalized cross-correlation score, from -1.0 to 1.0.
+    pub threshold: f64,
+    /// Template scale factors to try, e.g. `[0.75, 1.0, 1.5]` when the template was cut from
+    /// a device with a different density.
+    pub scales: Vec<f64>,
+    /// Part of the image to search, the whole image when `None`.
+    pub region: Option<Rect>,
+}
+
+impl Default for MatchOptions {
+    fn default() -> Self {
+        MatchOptions {
+            threshold: 0.9,
+            scales: vec![1.0],
+            region: None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TemplateMatch {
+    /// Where the (scaled) template matched, in image coordinates.
+    pub rect: Rect,
+    pub score: f64,
+    pub scale: f64,
+}
+
+/// Finds every non-overlapping occurrence of `template` in `dynamic_image` scoring at least
+/// the options' threshold, best first.
+///
+/// Scores are zero-mean normalized cross-correlation of the grayscale images, so they are
+/// insensitive to uniform brightness and contrast changes. Templates without any contrast
+/// never match.
+pub fn find_template(
+    dynamic_image: &DynamicImage, template: &DynamicImage, options: &MatchOptions,
+) -> Vec<TemplateMatch> {
+    let bounds = Rect::new(0, 0, dynamic_image.width(), dynamic_image.height());
+    let region = match options.region {
+        Some(region) => match region.intersection(&bounds) {
+            Some(region) => region,
+            None => return Vec::new(),
+        },
+        None => bounds,
+    };
+    let image = Plane::from_gray(
+        &dynamic_image
+            .crop_imm(region.x, region.y, region.width, region.height)
+            .to_luma8(),
+    );
+    let template = template.to_luma8();
+    let mut matches = Vec::new();
+    for &scale in &options.scales {
+        let width = (template.width() as f64 * scale).round() as u32;
+        let height = (template.height() as f64 * scale).round() as u32;
+        if width.min(height) < MIN_TEMPLATE_SIDE || width > region.width || height > region.height {
+            continue;
+        }
+        let scaled = Plane::from_gray(&resize(&template, width, height, FilterType::Triangle));
+        for (x, y, score) in image.find(&scaled, options.threshold) {
+            matches.push(TemplateMatch {
+                rect: Rect::new(x + region.x, y + region.y, width, height),
+                score,
+                scale,
+            });
+        }
+    }
+    matches.sort_by(|a, b| b.score.total_cmp(&a.score));
+    let mut kept: Vec<TemplateMatch> = Vec::new();
+    for candidate in matches {
+        if !kept.iter().any(|kept| kept.rect.intersects(&candidate.rect)) {
+            kept.push(candidate);
+        }
+    }
+    kept
+}
+
+/// Windows with less variance than this are flat, and never match.
+const MIN_VARIANCE: f64 = 1e-6;
+
+/// A grayscale image with summed-area tables, so the mean and variance under any window are
+/// constant-time lookups.
+struct Plane {
+    width: usize,
+    height: usize,
+    pixels: Vec<f64>,
+    sums: Vec<f64>,
+    squared_sums: Vec<f64>,
+}
+
+impl Plane {
+    fn from_gray(gray: &GrayImage) -> Self {
+        let pixels = gray.pixels().map(|pixel| pixel[0] as f64).collect();
+        Self::new(gray.width() as usize, gray.height() as usize, pixels)
+    }
+
+    fn new(width: usize, height: usize, pixels: Vec<f64>) -> Self {
+        let stride = width + 1;
+        let mut sums = vec![0.0; stride * (height + 1)];
+        let mut squared_sums = vec![0.0; stride * (height + 1)];
+        for y in 0..height {
+            let (mut row_sum, mut row_squared_sum) = (0.0, 0.0);
+            for x in 0..width {
+                let value: f64 = pixels[y * width + x];
+                row_sum += value;
+                row_squared_sum += value * value;
+                sums[(y + 1) * stride + x + 1] = sums[y * stride + x + 1] + row_sum;
+                squared_sums[(y + 1) * stride + x + 1] =
+                    squared_sums[y * stride + x + 1] + row_squared_sum;
+            }
+        }
+        Plane { width, height, pixels, sums, squared_sums }
+    }
+
+    /// Averages `factor`x`factor` blocks, dropping any remainder at the edges.
+    fn downscale(&self, factor: usize) -> Plane {
+        let (width, height) = (self.width / factor, self.height / factor);
+        let area = (factor * factor) as f64;
+        let pixels = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                self.window_sum(&self.sums, x * factor, y * factor, factor, factor) / area
+            })
+            .collect();
+        Plane::new(width, height, pixels)
+    }
+
+    fn window_sum(&self, table: &[f64], x: usize, y: usize, width: usize, height: usize) -> f64 {
+        let stride = self.width + 1;
+        table[(y + height) * stride + x + width] - table[y * stride + x + width]
+            - table[(y + height) * stride + x]
+            + table[y * stride + x]
+    }
+
+    /// Zero-mean normalized cross-correlation of `template` with the window at `x`/`y`.
+    fn score(&self, template: &Template, x: usize, y: usize) -> f64 {
+        let (width, height) = (template.plane.width, template.plane.height);
+        let count = (width * height) as f64;
+        let sum = self.window_sum(&self.sums, x, y, width, height);
+        let squared_sum = self.window_sum(&self.squared_sums, x, y, width, height);
+        let variance = squared_sum - sum * sum / count;
+        if variance < MIN_VARIANCE {
+            return 0.0;
+        }
+        let mut cross = 0.0;
+        for row in 0..height {
+            let image_row = &self.pixels[(y + row) * self.width + x..][..width];
+            let template_row = &template.centered[row * width..][..width];
+            cross += image_row.iter().zip(template_row).map(|(a, b)| a * b).sum::<f64>();
+        }
+        cross / (variance * template.variance).sqrt()
+    }
+
+    /// All scores of `template` at every position, row-major.
+    fn scores(&self, template: &Template) -> Vec<f64> {
+        let columns = self.width - template.plane.width + 1;
+        let rows = self.height - template.plane.height + 1;
+        (0..rows)
+            .flat_map(|y| (0..columns).map(move |x| (x, y)))
+            .map(|(x, y)| self.score(template, x, y))
+            .collect()
+    }
+
+    /// Matching positions and scores, searching coarsely first when the template is large
+    /// enough to be downscaled.
+    fn find(&self, template: &Plane, threshold: f64) -> Vec<(u32, u32, f64)> {
+        let Some(full) = Template::new(template) else {
+            return Vec::new();
+        };
+        let factor = (template.width.min(template.height) / COARSE_TEMPLATE_SIDE as usize).max(1);
+        if factor == 1 {
+            return local_maxima(&self.scores(&full), self.width - template.width + 1, threshold)
+                .into_iter()
+                .map(|(x, y, score)| (x as u32, y as u32, score))
+                .collect();
+        }
+        let coarse_image = self.downscale(factor);
+        let coarse_template_plane = template.downscale(factor);
+        let Some(coarse_template) = Template::new(&coarse_template_plane) else {
+            return Vec::new();
+        };
+        let columns = coarse_image.width - coarse_template.plane.width + 1;
+        let candidates = local_maxima(
+            &coarse_image.scores(&coarse_template), columns, threshold - COARSE_MARGIN,
+        );
+        let (max_x, max_y) = (self.width - template.width, self.height - template.height);
+        candidates
+            .into_iter()
+            .filter_map(|(coarse_x, coarse_y, _)| {
+                let (center_x, center_y) = (coarse_x * factor, coarse_y * factor);
+                let xs = center_x.saturating_sub(factor)..=(center_x + factor).min(max_x);
+                let ys = center_y.saturating_sub(factor)..=(center_y + factor).min(max_y);
+                ys.flat_map(|y| xs.clone().map(move |x| (x, y)))
+                    .map(|(x, y)| (x as u32, y as u32, self.score(&full, x, y)))
+                    .max_by(|a, b| a.2.total_cmp(&b.2))
+            })
+            .filter(|(_, _, score)| *score >= threshold)
+            .collect()
+    }
+}
+
+/// A template with its mean subtracted, as the cross-correlation needs it.
+struct Template<'a> {
+    plane: &'a Plane,
+    centered: Vec<f64>,
+    variance: f64,
+}
+
+impl<'a> Template<'a> {
+    /// `None` for templates without contrast, whose correlation is undefined.
+    fn new(plane: &'a Plane) -> Option<Self> {
+        let mean = plane.pixels.iter().sum::<f64>() / plane.pixels.len() as f64;
+        let centered: Vec<f64> = plane.pixels.iter().map(|value| value - mean).collect();
+        let variance = centered.iter().map(|value| value * value).sum::<f64>();
+        (variance >= MIN_VARIANCE).then_some(Template { plane, centered, variance })
+    }
+}
+
+/// Positions scoring at least `threshold` that no direct neighbour beats, best first.
+fn local_maxima(scores: &[f64], columns: usize, threshold: f64) -> Vec<(usize, usize, f64)> {
+    let rows = scores.len() / columns;
+    let mut maxima = Vec::new();
+    for y in 0..rows {
+        for x in 0..columns {
+            let score = scores[y * columns + x];
+            if score < threshold {
+                continue;
+            }
+            let xs = x.saturating_sub(1)..=(x + 1).min(columns - 1);
+            let neighbours = (y.saturating_sub(1)..=(y + 1).min(rows - 1))
+                .flat_map(|ny| xs.clone().map(move |nx| (nx, ny)));
+            let is_maximum = neighbours
+                .filter(|&(nx, ny)| (nx, ny) != (x, y))
+                .all(|(nx, ny)| scores[ny * columns + nx] <= score);
+            if is_maximum {
+                maxima.push((x, y, score));
+            }
+        }
+    }
+    maxima.sort_by(|a, b| b.2.total_cmp(&a.2));
+    maxima
+}
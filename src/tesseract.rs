@@ -9,6 +9,8 @@ use rusty_tesseract::{
     Args, DataOutput, Image,
 };
 
+use crate::geometry::Rect;
+
 const TESSERACT_CONFIG_VAR_NAME_CHAR_WHITELIST: &str = "tessedit_char_whitelist";
 const TESSERACT_LANG: &str = "eng";
 const TESSERACT_DPI: i32 = 120;
@@ -70,4 +72,29 @@ impl TesseractImage {
             None
         }
     }
+
+    /// Like [`TesseractImage::contains_text`], but only recognizes text within `region`.
+    pub fn contains_text_in(&self, text: &str, region: Rect) -> bool {
+        self.crop(region)
+            .is_some_and(|(cropped, _)| cropped.contains_text(text))
+    }
+
+    /// Like [`TesseractImage::xy_positions_of`], but only recognizes text within `region`.
+    /// Positions are still relative to the whole image.
+    pub fn xy_positions_of_in(&self, text: &str, region: Rect) -> Option<(Vec<i32>, Vec<i32>)> {
+        let (cropped, region) = self.crop(region)?;
+        let (x_positions, y_positions) = cropped.xy_positions_of(text)?;
+        Some((
+            x_positions.iter().map(|x| x + region.x as i32).collect(),
+            y_positions.iter().map(|y| y + region.y as i32).collect(),
+        ))
+    }
+
+    /// Crops to `region` clipped to the image bounds, returning the clipped region alongside.
+    fn crop(&self, region: Rect) -> Option<(TesseractImage, Rect)> {
+        let bounds = Rect::new(0, 0, self.dynamic_image.width(), self.dynamic_image.height());
+        let region = region.intersection(&bounds)?;
+        let cropped = self.dynamic_image.crop_imm(region.x, region.y, region.width, region.height);
+        Some((TesseractImage::from_dynamic_image(cropped), region))
+    }
 }
\ No newline at end of file
@@ -1,5 +1,41 @@
+use std::fmt;
+use std::io;
+use std::io::{Read, Write};
+use std::collections::HashMap;
+use std::fs;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use image::{DynamicImage, Rgba, RgbaImage};
+
 use crate::{Args, DarkInstruments};
-use crate::adb::Adb;
+use crate::adb::{Adb, DisplaySize};
+use crate::adb::display::{Insets, Rotation};
+use crate::adb::rotation::{CoordinateSpace, ScreenTransform};
+use crate::adb::screenrecord::{ScreenRecordOptions, ScreenRecording};
+use crate::color::{average_color, pixel_at, Color, ColorSignature, ColorTolerance, Hsv};
+use crate::diff::{diff_images, DiffOptions};
+use crate::device::{Device, DeviceState, RebootMode, WaitState};
+use crate::error::AdbError;
+use crate::frames::FrameStreamOptions;
+use crate::geometry::{Point, Rect};
+use crate::gesture::{Gesture, PointerPath};
+use crate::perceptual::{perceptual_hash, HashAlgorithm};
+use crate::process::ProcessRunner;
+use crate::template::{find_template, MatchOptions};
+use crate::runner::{
+    CancellationToken,
+    CommandOutput,
+    CommandRunner,
+    RunOptions,
+    ScriptedRunner,
+};
+use crate::screencap::{CaptureMode, ColorSpace};
+use crate::server::ServerRunner;
+use crate::tracker::DeviceEvent;
 
 #[test]
 fn test_dark_instruments_adb() {
@@ -24,10 +60,1712 @@ fn test_adb() {
         Ok(adb) => {
             println!("Have {:?}", adb);
             println!("Active target: {:?}", adb.have_active_target());
-            assert!(adb.have_active_target());
+            assert!(adb.have_active_target().unwrap());
         }
         Err(err) => {
             println!("{err}")
         }
     }
-}
\ No newline at end of file
+}
+
+fn scripted_adb(serial: &str) -> (Arc<ScriptedRunner>, Adb) {
+    let runner = Arc::new(ScriptedRunner::new());
+    runner.respond_stdout(
+        &["devices", "-l"],
+        &format!("List of devices attached\n{serial}\tdevice\n"),
+    );
+    let adb = Adb::with_runner(runner.clone(), Some(serial.to_string())).unwrap();
+    runner.clear_calls();
+    (runner, adb)
+}
+
+#[test]
+fn test_scripted_runner_records_input_tap() {
+    let (runner, adb) = scripted_adb("emulator-5554");
+    adb.input_tap(120, 640).unwrap();
+    assert_eq!(
+        runner.calls(),
+        vec![vec!["-s", "emulator-5554", "shell", "input", "tap", "120", "640"]],
+    );
+}
+
+#[test]
+fn test_scripted_runner_display_size() {
+    let (runner, adb) = scripted_adb("emulator-5554");
+    runner.respond_stdout(
+        &["-s", "emulator-5554", "shell", "wm", "size"],
+        "Physical size: 1080x2400",
+    );
+    let display_size = adb.display_size().unwrap();
+    assert_eq!((display_size.width, display_size.height), (1080, 2400));
+}
+
+#[test]
+fn test_scripted_runner_start_activity() {
+    let (runner, adb) = scripted_adb("emulator-5554");
+    adb.start_activity("com.example/.MainActivity").unwrap();
+    assert_eq!(
+        runner.calls(),
+        vec![vec![
+            "-s", "emulator-5554", "shell", "am", "start", "-n", "com.example/.MainActivity",
+        ]],
+    );
+}
+
+#[test]
+fn test_scripted_runner_target_not_online() {
+    let runner = Arc::new(ScriptedRunner::new());
+    runner.respond_stdout(&["devices", "-l"], "List of devices attached\n");
+    assert!(matches!(
+        Adb::with_runner(runner, Some("emulator-5554".to_string())),
+        Err(AdbError::TargetNotOnline)
+    ));
+}
+
+#[test]
+fn test_non_zero_exit_is_reported() {
+    let (runner, adb) = scripted_adb("emulator-5554");
+    runner.respond(
+        &["-s", "emulator-5554", "shell", "input", "text", "hello"],
+        CommandOutput::new(Vec::new(), b"input: permission denied".to_vec(), Some(1)),
+    );
+    match adb.input_text("hello") {
+        Err(AdbError::NonZeroExit { exit_code, stderr }) => {
+            assert_eq!(exit_code, Some(1));
+            assert_eq!(stderr, "input: permission denied");
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn test_device_state_errors_are_parsed_from_stderr() {
+    let (runner, adb) = scripted_adb("emulator-5554");
+    runner.respond(
+        &["-s", "emulator-5554", "shell", "input", "tap", "1", "1"],
+        CommandOutput::new(Vec::new(), b"error: device offline".to_vec(), Some(1)),
+    );
+    runner.respond(
+        &["-s", "emulator-5554", "shell", "input", "tap", "2", "2"],
+        CommandOutput::new(Vec::new(), b"error: device unauthorized.".to_vec(), Some(1)),
+    );
+    assert!(matches!(adb.input_tap(1, 1), Err(AdbError::DeviceOffline)));
+    assert!(matches!(adb.input_tap(2, 2), Err(AdbError::DeviceUnauthorized)));
+}
+
+#[test]
+fn test_start_activity_error_output() {
+    let (runner, adb) = scripted_adb("emulator-5554");
+    runner.respond_stdout(
+        &["-s", "emulator-5554", "shell", "am", "start", "-n", "com.example/.Missing"],
+        "Starting: Intent { cmp=com.example/.Missing }\n\
+         Error type 3\n\
+         Error: Activity class does not exist.",
+    );
+    assert!(matches!(
+        adb.start_activity("com.example/.Missing"),
+        Err(AdbError::CommandFailed(_))
+    ));
+}
+
+#[test]
+fn test_display_size_prefers_override() {
+    let (runner, adb) = scripted_adb("emulator-5554");
+    runner.respond_stdout(
+        &["-s", "emulator-5554", "shell", "wm", "size"],
+        "Physical size: 1080x2400\nOverride size: 720x1600",
+    );
+    assert_eq!(adb.display_size().unwrap(), DisplaySize { width: 720, height: 1600 });
+}
+
+#[test]
+fn test_unparseable_display_size() {
+    let (runner, adb) = scripted_adb("emulator-5554");
+    runner.respond_stdout(&["-s", "emulator-5554", "shell", "wm", "size"], "unknown");
+    assert!(matches!(adb.display_size(), Err(AdbError::UnparseableOutput(_))));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_process_runner_timeout_kills_child() {
+    let runner = ProcessRunner::new(PathBuf::from("sleep"));
+    let options = RunOptions {
+        timeout: Some(Duration::from_millis(100)),
+        cancellation: None,
+    };
+    let started = Instant::now();
+    let err = runner.run(&["5"], &options).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    assert!(started.elapsed() < Duration::from_secs(2));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_process_runner_cancellation_from_another_thread() {
+    let runner = ProcessRunner::new(PathBuf::from("sleep"));
+    let cancellation = CancellationToken::new();
+    let options = RunOptions {
+        timeout: None,
+        cancellation: Some(cancellation.clone()),
+    };
+    let canceller = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(100));
+        cancellation.cancel();
+    });
+    let err = runner.run(&["5"], &options).unwrap_err();
+    canceller.join().unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::Interrupted);
+}
+
+#[test]
+fn test_cancelled_adb_command() {
+    let (_, adb) = scripted_adb("emulator-5554");
+    let cancellation = CancellationToken::new();
+    cancellation.cancel();
+    assert!(matches!(
+        adb.with_cancellation(cancellation).input_tap(1, 1),
+        Err(AdbError::Cancelled)
+    ));
+}
+
+#[test]
+fn test_sensitive_arguments_are_redacted() {
+    let (_, adb) = scripted_adb("emulator-5554");
+    let args = ["-s", "emulator-5554", "shell", "input", "text", "hunter2"];
+    assert_eq!(
+        adb.loggable_args(&args, &[5]),
+        "-s emulator-5554 shell input text <redacted>",
+    );
+    assert_eq!(
+        adb.with_argument_redaction(false).loggable_args(&args, &[5]),
+        "-s emulator-5554 shell input text hunter2",
+    );
+}
+
+/// Records the `args` field of every span created while it is the default subscriber.
+#[derive(Default)]
+struct SpanArgs(Mutex<Vec<String>>);
+
+impl tracing::Subscriber for SpanArgs {
+    fn enabled(&self, _: &tracing::Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, attributes: &tracing::span::Attributes<'_>) -> tracing::span::Id {
+        struct Visitor<'a>(&'a Mutex<Vec<String>>);
+        impl tracing::field::Visit for Visitor<'_> {
+            fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn fmt::Debug) {
+                if field.name() == "args" {
+                    self.0.lock().unwrap().push(format!("{:?}", value));
+                }
+            }
+        }
+        attributes.record(&mut Visitor(&self.0));
+        tracing::span::Id::from_u64(1)
+    }
+
+    fn record(&self, _: &tracing::span::Id, _: &tracing::span::Record<'_>) {}
+
+    fn record_follows_from(&self, _: &tracing::span::Id, _: &tracing::span::Id) {}
+
+    fn event(&self, _: &tracing::Event<'_>) {}
+
+    fn enter(&self, _: &tracing::span::Id) {}
+
+    fn exit(&self, _: &tracing::span::Id) {}
+}
+
+#[test]
+fn test_input_text_span_redacts_payload() {
+    let (_, adb) = scripted_adb("emulator-5554");
+    let subscriber = Arc::new(SpanArgs::default());
+    tracing::subscriber::with_default(subscriber.clone(), || {
+        adb.input_text("hunter2").unwrap();
+        adb.with_argument_redaction(false).input_text("hunter2").unwrap();
+    });
+    assert_eq!(*subscriber.0.lock().unwrap(), [
+        "-s emulator-5554 shell input text <redacted>",
+        "-s emulator-5554 shell input text hunter2",
+    ]);
+}
+
+#[cfg(feature = "tokio")]
+mod async_adb {
+    use std::path::PathBuf;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use crate::async_adb::{AsyncAdb, AsyncCommandRunner, TokioProcessRunner};
+    use crate::error::AdbError;
+    use crate::runner::{RunOptions, ScriptedRunner};
+
+    async fn scripted_async_adb(serial: &str) -> (Arc<ScriptedRunner>, AsyncAdb) {
+        let runner = Arc::new(ScriptedRunner::new());
+        runner.respond_stdout(
+            &["devices", "-l"],
+            &format!("List of devices attached\n{serial}\tdevice\n"),
+        );
+        let adb = AsyncAdb::with_runner(runner.clone(), Some(serial.to_string()))
+            .await
+            .unwrap();
+        runner.clear_calls();
+        (runner, adb)
+    }
+
+    #[tokio::test]
+    async fn test_async_input_tap_and_display_size() {
+        let (runner, adb) = scripted_async_adb("emulator-5556").await;
+        runner.respond_stdout(
+            &["-s", "emulator-5556", "shell", "wm", "size"],
+            "Physical size: 720x1280",
+        );
+        adb.input_tap(5, 6).await.unwrap();
+        let display_size = adb.display_size().await.unwrap();
+        assert_eq!((display_size.width, display_size.height), (720, 1280));
+        assert_eq!(
+            runner.calls()[0],
+            vec!["-s", "emulator-5556", "shell", "input", "tap", "5", "6"],
+        );
+    }
+
+    #[tokio::test]
+    async fn test_async_input_text_span_redacts_payload() {
+        let (runner, targeted) = scripted_async_adb("emulator-5556").await;
+        let untargeted = AsyncAdb::with_runner(runner, None).await.unwrap();
+        let subscriber = Arc::new(super::SpanArgs::default());
+        let _default = tracing::subscriber::set_default(subscriber.clone());
+        targeted.input_text("hunter2").await.unwrap();
+        untargeted.input_text("hunter2").await.unwrap();
+        assert_eq!(*subscriber.0.lock().unwrap(), [
+            "-s emulator-5556 shell input text <redacted>",
+            "shell input text <redacted>",
+        ]);
+    }
+
+    #[tokio::test]
+    async fn test_async_devices_are_driven_concurrently() {
+        let (first_runner, first) = scripted_async_adb("first").await;
+        let (second_runner, second) = scripted_async_adb("second").await;
+        let (first_result, second_result) = tokio::join!(
+            first.input_tap(1, 1),
+            second.input_tap(2, 2),
+        );
+        first_result.unwrap();
+        second_result.unwrap();
+        assert_eq!(first_runner.calls().len(), 1);
+        assert_eq!(second_runner.calls().len(), 1);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_tokio_process_runner_timeout() {
+        let runner = TokioProcessRunner::new(PathBuf::from("sleep"));
+        let options = RunOptions {
+            timeout: Some(Duration::from_millis(100)),
+            cancellation: None,
+        };
+        let err = runner.run(&["5"], &options).await.unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+    }
+
+    #[tokio::test]
+    async fn test_async_target_not_online() {
+        let runner = Arc::new(ScriptedRunner::new());
+        runner.respond_stdout(&["devices", "-l"], "List of devices attached\n");
+        assert!(matches!(
+            AsyncAdb::with_runner(runner, None).await,
+            Err(AdbError::NoExclusiveTargetOnline)
+        ));
+    }
+}
+
+enum Reply {
+    Okay,
+    Payload(String),
+    Stream(Vec<u8>),
+    Fail(String),
+    Handoff(Box<dyn FnOnce(&mut TcpStream) + Send>),
+}
+
+fn fake_adb_server(
+    handler: impl Fn(&str) -> Reply + Send + Sync + 'static,
+) -> (SocketAddr, Arc<Mutex<Vec<String>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let services = Arc::new(Mutex::new(Vec::new()));
+    let recorded = services.clone();
+    let handler = Arc::new(handler);
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else { return };
+            let handler = handler.clone();
+            let recorded = recorded.clone();
+            thread::spawn(move || loop {
+                let mut length = [0; 4];
+                if stream.read_exact(&mut length).is_err() {
+                    return;
+                }
+                let length = usize::from_str_radix(std::str::from_utf8(&length).unwrap(), 16)
+                    .unwrap();
+                let mut service = vec![0; length];
+                stream.read_exact(&mut service).unwrap();
+                let service = String::from_utf8(service).unwrap();
+                recorded.lock().unwrap().push(service.clone());
+                let bytes = match handler(&service) {
+                    Reply::Okay => {
+                        stream.write_all(b"OKAY").unwrap();
+                        continue;
+                    }
+                    Reply::Payload(payload) => {
+                        format!("OKAY{:04x}{}", payload.len(), payload).into_bytes()
+                    }
+                    Reply::Stream(bytes) => [b"OKAY".as_slice(), &bytes].concat(),
+                    Reply::Fail(message) => {
+                        format!("FAIL{:04x}{}", message.len(), message).into_bytes()
+                    }
+                    Reply::Handoff(session) => {
+                        stream.write_all(b"OKAY").unwrap();
+                        session(&mut stream);
+                        return;
+                    }
+                };
+                let _ = stream.write_all(&bytes);
+                return;
+            });
+        }
+    });
+    (addr, services)
+}
+
+fn shell_v2_packet(id: u8, payload: &[u8]) -> Vec<u8> {
+    [&[id], &(payload.len() as u32).to_le_bytes()[..], payload].concat()
+}
+
+fn fake_device_server(
+    handler: impl Fn(&str) -> Reply + Send + Sync + 'static,
+) -> (SocketAddr, Arc<Mutex<Vec<String>>>) {
+    fake_adb_server(move |service| match service {
+        "host:devices-l" => Reply::Payload("emulator-5554\tdevice\n".to_string()),
+        "host:version" => Reply::Payload("0029".to_string()),
+        "host:transport:emulator-5554" => Reply::Okay,
+        _ => handler(service),
+    })
+}
+
+#[test]
+fn test_server_runner_shell_and_exec_out() {
+    let (addr, services) = fake_device_server(|service| match service {
+        "shell,v2,raw:input tap 3 4" => Reply::Stream(shell_v2_packet(3, &[0])),
+        "exec:screencap -p" => Reply::Stream(b"\x89PNG".to_vec()),
+        _ => Reply::Fail(format!("unexpected {service}")),
+    });
+    let adb = Adb::with_server(addr, Some("emulator-5554".to_string())).unwrap();
+    adb.input_tap(3, 4).unwrap();
+    assert_eq!(adb.capture_screen_as_bytes().unwrap(), b"\x89PNG");
+    let services = services.lock().unwrap();
+    assert!(services.contains(&"shell,v2,raw:input tap 3 4".to_string()));
+    assert!(services.contains(&"exec:screencap -p".to_string()));
+}
+
+#[test]
+fn test_server_runner_shell_v2_exit_code_and_stderr() {
+    let (addr, _) = fake_device_server(|_| {
+        Reply::Stream([
+            shell_v2_packet(1, b"partial"),
+            shell_v2_packet(2, b"input: bad argument"),
+            shell_v2_packet(3, &[2]),
+        ].concat())
+    });
+    let adb = Adb::with_server(addr, Some("emulator-5554".to_string())).unwrap();
+    match adb.input_text("x") {
+        Err(AdbError::NonZeroExit { exit_code, stderr }) => {
+            assert_eq!(exit_code, Some(2));
+            assert_eq!(stderr, "input: bad argument");
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn test_server_runner_failures_map_to_device_errors() {
+    let (addr, _) = fake_adb_server(|service| match service {
+        "host:devices-l" => Reply::Payload("emulator-5554\toffline\n".to_string()),
+        "host:version" => Reply::Payload("0029".to_string()),
+        _ => Reply::Fail("device offline".to_string()),
+    });
+    let runner = ServerRunner::new(addr);
+    let adb = Adb::with_runner(Arc::new(runner), Some("emulator-5554".to_string())).unwrap();
+    assert!(matches!(adb.input_tap(1, 1), Err(AdbError::DeviceOffline)));
+}
+
+#[derive(Clone)]
+struct FakeFile {
+    mode: u32,
+    mtime: u32,
+    data: Vec<u8>,
+}
+
+type FakeFs = Arc<Mutex<HashMap<String, FakeFile>>>;
+
+fn fake_file(mode: u32, mtime: u32, data: &[u8]) -> FakeFile {
+    FakeFile { mode, mtime, data: data.to_vec() }
+}
+
+fn read_u32_le(stream: &mut TcpStream) -> u32 {
+    let mut bytes = [0; 4];
+    stream.read_exact(&mut bytes).unwrap();
+    u32::from_le_bytes(bytes)
+}
+
+fn read_string(stream: &mut TcpStream, length: u32) -> String {
+    let mut bytes = vec![0; length as usize];
+    stream.read_exact(&mut bytes).unwrap();
+    String::from_utf8(bytes).unwrap()
+}
+
+fn write_words(stream: &mut TcpStream, id: &[u8], words: &[u32]) {
+    let mut bytes = id.to_vec();
+    words.iter().for_each(|word| bytes.extend(word.to_le_bytes()));
+    stream.write_all(&bytes).unwrap();
+}
+
+fn fake_sync_session(files: FakeFs, stream: &mut TcpStream) {
+    loop {
+        let mut id = [0; 4];
+        if stream.read_exact(&mut id).is_err() {
+            return;
+        }
+        let length = read_u32_le(stream);
+        match &id {
+            b"STAT" => {
+                let path = read_string(stream, length);
+                let files = files.lock().unwrap();
+                match files.get(&path) {
+                    Some(file) => {
+                        let size = file.data.len() as u32;
+                        write_words(stream, b"STAT", &[file.mode, size, file.mtime])
+                    }
+                    None if files.keys().any(|key| key.starts_with(&format!("{path}/"))) => {
+                        write_words(stream, b"STAT", &[0o040755, 0, 0])
+                    }
+                    None => write_words(stream, b"STAT", &[0, 0, 0]),
+                }
+            }
+            b"LIST" => {
+                let path = read_string(stream, length);
+                let prefix = format!("{path}/");
+                let files = files.lock().unwrap();
+                for (key, file) in files.iter().filter(|(key, _)| key.starts_with(&prefix)) {
+                    let name = &key[prefix.len()..];
+                    write_words(
+                        stream, b"DENT",
+                        &[file.mode, file.data.len() as u32, file.mtime, name.len() as u32],
+                    );
+                    stream.write_all(name.as_bytes()).unwrap();
+                }
+                write_words(stream, b"DONE", &[0, 0, 0, 0]);
+            }
+            b"RECV" => {
+                let path = read_string(stream, length);
+                match files.lock().unwrap().get(&path) {
+                    // Unreadable files fail after their first chunk, like a read error.
+                    Some(file) if file.mode & 0o777 == 0 => {
+                        write_words(stream, b"DATA", &[3]);
+                        stream.write_all(&file.data[..3]).unwrap();
+                        let message = "Permission denied";
+                        write_words(stream, b"FAIL", &[message.len() as u32]);
+                        stream.write_all(message.as_bytes()).unwrap();
+                    }
+                    Some(file) => {
+                        for chunk in file.data.chunks(3) {
+                            write_words(stream, b"DATA", &[chunk.len() as u32]);
+                            stream.write_all(chunk).unwrap();
+                        }
+                        write_words(stream, b"DONE", &[0]);
+                    }
+                    None => {
+                        let message = "No such file or directory";
+                        write_words(stream, b"FAIL", &[message.len() as u32]);
+                        stream.write_all(message.as_bytes()).unwrap();
+                    }
+                }
+            }
+            b"SEND" => {
+                let path_and_mode = read_string(stream, length);
+                let (path, mode) = path_and_mode.rsplit_once(',').unwrap();
+                let mut data = Vec::new();
+                let mtime = loop {
+                    let mut id = [0; 4];
+                    stream.read_exact(&mut id).unwrap();
+                    let length = read_u32_le(stream);
+                    match &id {
+                        b"DATA" => {
+                            let mut chunk = vec![0; length as usize];
+                            stream.read_exact(&mut chunk).unwrap();
+                            data.extend(chunk);
+                        }
+                        _ => break length,
+                    }
+                };
+                files.lock().unwrap().insert(path.to_string(), FakeFile {
+                    mode: mode.parse().unwrap(),
+                    mtime,
+                    data,
+                });
+                write_words(stream, b"OKAY", &[0]);
+            }
+            _ => return,
+        }
+    }
+}
+
+fn fake_sync_server(files: FakeFs) -> SocketAddr {
+    let (addr, _) = fake_device_server(move |service| match service {
+        "sync:" => {
+            let files = files.clone();
+            Reply::Handoff(Box::new(move |stream| fake_sync_session(files, stream)))
+        }
+        _ => Reply::Fail(format!("unexpected {service}")),
+    });
+    addr
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir()
+        .join(format!("dark_instruments_{}_{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn test_sync_stat_and_list_dir() {
+    let files: FakeFs = Arc::new(Mutex::new(HashMap::from([
+        ("/sdcard/a.txt".to_string(), fake_file(0o100644, 1_700_000_000, b"abc")),
+        ("/sdcard/b.txt".to_string(), fake_file(0o100600, 1_700_000_001, b"de")),
+    ])));
+    let adb = Adb::with_server(fake_sync_server(files), Some("emulator-5554".to_string())).unwrap();
+    let stat = adb.stat("/sdcard/a.txt").unwrap();
+    assert!(stat.is_file());
+    assert_eq!(stat.size, 3);
+    assert_eq!(stat.mtime, UNIX_EPOCH + Duration::from_secs(1_700_000_000));
+    assert!(adb.stat("/sdcard").unwrap().is_dir());
+    assert!(!adb.stat("/missing").unwrap().exists());
+    let mut names: Vec<String> = adb.list_dir("/sdcard").unwrap()
+        .into_iter()
+        .map(|entry| entry.name)
+        .collect();
+    names.sort();
+    assert_eq!(names, vec!["a.txt", "b.txt"]);
+}
+
+#[test]
+fn test_sync_push_and_pull_round_trip() {
+    let files: FakeFs = Arc::new(Mutex::new(HashMap::new()));
+    let adb = Adb::with_server(
+        fake_sync_server(files.clone()), Some("emulator-5554".to_string()),
+    ).unwrap();
+    let local_dir = temp_dir("sync_round_trip");
+    let source = local_dir.join("fixture.bin");
+    fs::write(&source, b"fixture payload").unwrap();
+    let modified = UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+    fs::File::options().write(true).open(&source).unwrap().set_modified(modified).unwrap();
+
+    let mut progress = Vec::new();
+    adb.push_with_progress(&source, "/data/local/tmp/fixture.bin", |update| {
+        progress.push(update.clone())
+    }).unwrap();
+    let pushed = files.lock().unwrap()["/data/local/tmp/fixture.bin"].clone();
+    assert_eq!(pushed.data, b"fixture payload");
+    assert_eq!(pushed.mtime, 1_600_000_000);
+    assert_eq!(progress.last().unwrap().transferred, 15);
+    assert_eq!(progress.last().unwrap().total, 15);
+
+    let pulled = local_dir.join("pulled.bin");
+    adb.pull("/data/local/tmp/fixture.bin", &pulled).unwrap();
+    assert_eq!(fs::read(&pulled).unwrap(), b"fixture payload");
+    assert_eq!(fs::metadata(&pulled).unwrap().modified().unwrap(), modified);
+    fs::remove_dir_all(&local_dir).unwrap();
+}
+
+#[test]
+fn test_sync_pull_directory_and_missing_file() {
+    let files: FakeFs = Arc::new(Mutex::new(HashMap::from([
+        ("/sdcard/shots/1.png".to_string(), fake_file(0o100644, 1, b"one")),
+        ("/sdcard/shots/2.png".to_string(), fake_file(0o100644, 2, b"two")),
+    ])));
+    let adb = Adb::with_server(fake_sync_server(files), Some("emulator-5554".to_string())).unwrap();
+    let local_dir = temp_dir("sync_pull_directory");
+    adb.pull("/sdcard/shots", &local_dir).unwrap();
+    assert_eq!(fs::read(local_dir.join("shots/1.png")).unwrap(), b"one");
+    assert_eq!(fs::read(local_dir.join("shots/2.png")).unwrap(), b"two");
+    assert!(matches!(
+        adb.pull("/sdcard/missing.png", &local_dir),
+        Err(AdbError::ServerFailure(_))
+    ));
+    fs::remove_dir_all(&local_dir).unwrap();
+}
+
+#[test]
+fn test_sync_failed_pull_removes_partial_file() {
+    let files: FakeFs = Arc::new(Mutex::new(HashMap::from([
+        ("/sdcard/secret.db".to_string(), fake_file(0o100000, 1, b"partial contents")),
+    ])));
+    let adb = Adb::with_server(fake_sync_server(files), Some("emulator-5554".to_string())).unwrap();
+    let local_dir = temp_dir("sync_failed_pull");
+    assert!(matches!(
+        adb.pull("/sdcard/secret.db", &local_dir),
+        Err(AdbError::ServerFailure(_))
+    ));
+    assert!(!local_dir.join("secret.db").exists());
+    fs::remove_dir_all(&local_dir).unwrap();
+}
+
+#[test]
+fn test_sync_push_creates_empty_directories() {
+    let files: FakeFs = Arc::new(Mutex::new(HashMap::new()));
+    let (runner, adb) = scripted_adb("emulator-5554");
+    let adb = adb.with_server_addr(fake_sync_server(files.clone()));
+    let local_dir = temp_dir("sync_push_empty_dirs").join("fixtures");
+    fs::create_dir_all(local_dir.join("empty")).unwrap();
+    fs::create_dir_all(local_dir.join("full")).unwrap();
+    fs::write(local_dir.join("full/a.txt"), b"a").unwrap();
+
+    adb.push(&local_dir, "/data/local/tmp/fixtures").unwrap();
+    assert_eq!(files.lock().unwrap()["/data/local/tmp/fixtures/full/a.txt"].data, b"a");
+    assert_eq!(runner.calls(), [[
+        "-s", "emulator-5554", "shell", "mkdir", "-p", "'/data/local/tmp/fixtures/empty'",
+    ].map(str::to_string).to_vec()]);
+    fs::remove_dir_all(local_dir.parent().unwrap()).unwrap();
+}
+
+#[test]
+fn test_list_devices_parses_long_listing() {
+    let (runner, adb) = scripted_adb("emulator-5554");
+    runner.respond_stdout(
+        &["devices", "-l"],
+        "List of devices attached\n\
+         emulator-5554          device product:sdk_gphone64 model:sdk_gphone64 \
+         device:emu64xa transport_id:1\n\
+         R58M123ABC             unauthorized usb:1-1 transport_id:4\n\
+         0123456789ABCDEF       no permissions (missing udev rules?); see [http://x]\n\
+         192.168.1.20:5555      offline transport_id:7\n",
+    );
+    let devices = adb.list_devices().unwrap();
+    assert_eq!(devices.len(), 4);
+    assert_eq!(devices[0], Device {
+        serial: "emulator-5554".to_string(),
+        state: DeviceState::Device,
+        product: Some("sdk_gphone64".to_string()),
+        model: Some("sdk_gphone64".to_string()),
+        device: Some("emu64xa".to_string()),
+        transport_id: Some(1),
+    });
+    assert_eq!(devices[1].state, DeviceState::Unauthorized);
+    assert_eq!(devices[1].transport_id, Some(4));
+    assert_eq!(devices[2].state, DeviceState::NoPermissions);
+    assert_eq!(devices[3].serial, "192.168.1.20:5555");
+    assert_eq!(devices[3].state, DeviceState::Offline);
+}
+
+#[test]
+fn test_have_target_matches_exact_serials() {
+    let (runner, adb) = scripted_adb("emulator-5554");
+    runner.respond_stdout(
+        &["devices", "-l"],
+        "List of devices attached\nemulator-5554\tdevice transport_id:1\n",
+    );
+    assert!(adb.have_target("emulator-5554").unwrap());
+    assert!(!adb.have_target("emulator-55").unwrap());
+}
+
+#[test]
+fn test_have_active_target_ignores_offline_devices() {
+    let (runner, adb) = scripted_adb("emulator-5554");
+    runner.respond_stdout(
+        &["devices", "-l"],
+        "List of devices attached\nemulator-5554\tdevice\nemulator-5556\toffline\n",
+    );
+    assert!(adb.have_active_target().unwrap());
+}
+
+#[test]
+fn test_with_runner_when_ready_waits_for_device() {
+    let runner = Arc::new(ScriptedRunner::new());
+    runner
+        .respond_stdout(&["devices", "-l"], "List of devices attached\n")
+        .respond_stdout(&["devices", "-l"], "List of devices attached\nserial-1\toffline\n")
+        .respond_stdout(&["devices", "-l"], "List of devices attached\nserial-1\tdevice\n");
+    let adb = Adb::with_runner_when_ready(
+        runner.clone(), Some("serial-1".to_string()), Duration::from_secs(5),
+    ).unwrap();
+    assert_eq!(adb.target_serial(), Some("serial-1"));
+    let polls = runner.calls().iter().filter(|call| call[0] == "devices").count();
+    assert_eq!(polls, 3);
+}
+
+#[test]
+fn test_wait_for_state_times_out() {
+    let (_, adb) = scripted_adb("emulator-5554");
+    let started = Instant::now();
+    assert!(matches!(
+        adb.wait_for_state(WaitState::Disconnect, Duration::from_millis(300)),
+        Err(AdbError::Timeout(_))
+    ));
+    assert!(started.elapsed() >= Duration::from_millis(300));
+}
+
+#[test]
+fn test_wait_for_recovery_and_disconnect() {
+    let (runner, adb) = scripted_adb("emulator-5554");
+    runner.respond_stdout(
+        &["devices", "-l"],
+        "List of devices attached\nemulator-5554\trecovery\n",
+    );
+    adb.wait_for_state(WaitState::Recovery, Duration::from_secs(1)).unwrap();
+    runner.respond_stdout(&["devices", "-l"], "List of devices attached\n");
+    adb.wait_for_state(WaitState::Disconnect, Duration::from_secs(1)).unwrap();
+    runner.respond_stdout(
+        &["devices", "-l"],
+        "List of devices attached\nemulator-5554\tbootloader\n",
+    );
+    adb.wait_for_state(WaitState::Bootloader, Duration::from_secs(1)).unwrap();
+}
+
+#[test]
+fn test_wait_for_boot_completed_polls_properties() {
+    let (runner, adb) = scripted_adb("emulator-5554");
+    let boot_completed = ["-s", "emulator-5554", "shell", "getprop", "sys.boot_completed"];
+    let boot_animation = ["-s", "emulator-5554", "shell", "getprop", "init.svc.bootanim"];
+    runner
+        .respond_stdout(&boot_completed, "")
+        .respond_stdout(&boot_completed, "1");
+    runner
+        .respond_stdout(&boot_animation, "running")
+        .respond_stdout(&boot_animation, "running")
+        .respond_stdout(&boot_animation, "stopped");
+    adb.wait_for_boot_completed(Duration::from_secs(5)).unwrap();
+    let property_polls = runner.calls()
+        .iter()
+        .filter(|call| call.get(3).is_some_and(|arg| arg == "getprop"))
+        .count();
+    assert_eq!(property_polls, 6);
+}
+
+#[test]
+fn test_connect_and_disconnect_network_device() {
+    let runner = Arc::new(ScriptedRunner::new());
+    runner
+        .respond_stdout(&["connect", "192.168.1.20:5555"], "connected to 192.168.1.20:5555")
+        .respond_stdout(
+            &["connect", "192.168.1.21:5555"],
+            "failed to connect to '192.168.1.21:5555': Connection refused",
+        )
+        .respond_stdout(&["disconnect", "192.168.1.20:5555"], "disconnected 192.168.1.20:5555");
+    let adb = Adb::host_with_runner(runner.clone()).unwrap();
+    assert_eq!(adb.connect("192.168.1.20", None).unwrap(), "192.168.1.20:5555");
+    match adb.connect("192.168.1.21", Some(5555)) {
+        Err(AdbError::CommandFailed(message)) => assert!(message.contains("Connection refused")),
+        other => panic!("unexpected result: {:?}", other),
+    }
+    adb.disconnect(Some("192.168.1.20:5555")).unwrap();
+}
+
+#[test]
+fn test_pair_and_tcpip() {
+    let (runner, adb) = scripted_adb("R58M123ABC");
+    runner
+        .respond_stdout(
+            &["pair", "192.168.1.20:37099", "123456"],
+            "Successfully paired to 192.168.1.20:37099 [guid=adb-R58M123ABC]",
+        )
+        .respond_stdout(
+            &["-s", "R58M123ABC", "tcpip", "5555"],
+            "restarting in TCP mode port: 5555",
+        );
+    adb.pair("192.168.1.20:37099", "123456").unwrap();
+    adb.tcpip(5555).unwrap();
+    runner.respond(
+        &["pair", "192.168.1.20:37099", "000000"],
+        CommandOutput::new(b"Failed: Wrong password.".to_vec(), Vec::new(), Some(1)),
+    );
+    assert!(matches!(
+        adb.pair("192.168.1.20:37099", "000000"),
+        Err(AdbError::CommandFailed(_))
+    ));
+}
+
+#[test]
+fn test_server_runner_connect() {
+    let (addr, services) = fake_adb_server(|service| match service {
+        "host:version" => Reply::Payload("0029".to_string()),
+        "host:connect:10.0.0.5:5555" => {
+            Reply::Payload("already connected to 10.0.0.5:5555".to_string())
+        }
+        _ => Reply::Fail(format!("unexpected {service}")),
+    });
+    let adb = Adb::host_with_runner(Arc::new(ServerRunner::new(addr))).unwrap();
+    assert_eq!(adb.connect("10.0.0.5", None).unwrap(), "10.0.0.5:5555");
+    assert!(services.lock().unwrap().contains(&"host:connect:10.0.0.5:5555".to_string()));
+}
+
+fn write_length_prefixed(stream: &mut TcpStream, payload: &str) {
+    stream.write_all(format!("{:04x}{}", payload.len(), payload).as_bytes()).unwrap();
+}
+
+#[test]
+fn test_track_devices_reports_hotplug_events() {
+    let (addr, _) = fake_device_server(|service| match service {
+        "host:track-devices-l" => Reply::Handoff(Box::new(|stream| {
+            write_length_prefixed(stream, "emulator-5554\tdevice transport_id:1\n");
+            write_length_prefixed(
+                stream,
+                "emulator-5554\tdevice transport_id:1\nR58M123ABC\tunauthorized\n",
+            );
+            write_length_prefixed(
+                stream,
+                "emulator-5554\tdevice transport_id:1\nR58M123ABC\tdevice\n",
+            );
+            write_length_prefixed(stream, "R58M123ABC\tdevice\n");
+            thread::sleep(Duration::from_secs(5));
+        })),
+        _ => Reply::Fail(format!("unexpected {service}")),
+    });
+    let adb = Adb::with_server(addr, Some("emulator-5554".to_string())).unwrap();
+    let tracker = adb.track_devices().unwrap();
+    let events: Vec<DeviceEvent> = tracker.take(4).map(Result::unwrap).collect();
+    assert!(matches!(&events[0], DeviceEvent::Added(device) if device.serial == "emulator-5554"));
+    assert!(matches!(
+        &events[1],
+        DeviceEvent::Added(device) if device.state == DeviceState::Unauthorized
+    ));
+    assert!(matches!(
+        &events[2],
+        DeviceEvent::StateChanged { device, previous: DeviceState::Unauthorized }
+            if device.serial == "R58M123ABC" && device.is_online()
+    ));
+    assert!(matches!(&events[3], DeviceEvent::Removed(device) if device.serial == "emulator-5554"));
+}
+
+#[test]
+fn test_track_devices_ends_with_error_when_server_disconnects() {
+    let (addr, _) = fake_device_server(|service| match service {
+        "host:track-devices-l" => Reply::Handoff(Box::new(|stream| {
+            write_length_prefixed(stream, "");
+        })),
+        _ => Reply::Fail(format!("unexpected {service}")),
+    });
+    let adb = Adb::with_server(addr, Some("emulator-5554".to_string())).unwrap();
+    let mut tracker = adb.track_devices().unwrap();
+    assert!(matches!(tracker.next(), Some(Err(AdbError::Io(_)))));
+    assert!(tracker.next().is_none());
+}
+
+#[test]
+fn test_properties_are_parsed_and_cached() {
+    let (runner, adb) = scripted_adb("emulator-5554");
+    let getprop = ["-s", "emulator-5554", "shell", "getprop"];
+    runner.respond_stdout(
+        &getprop,
+        "[ro.build.version.sdk]: [34]\n\
+         [ro.build.version.release]: [14]\n\
+         [ro.product.manufacturer]: [Google]\n\
+         [ro.product.model]: [Pixel 7]\n\
+         [ro.product.cpu.abilist]: [arm64-v8a,armeabi-v7a,armeabi]\n\
+         [ro.build.fingerprint]: [google/panther/panther:14/UQ1A/1:user/release-keys]\n\
+         [persist.sys.locale]: [en-US]\n\
+         [ro.empty]: []\n",
+    );
+    let info = adb.device_info().unwrap();
+    assert_eq!(info.sdk_level, Some(34));
+    assert_eq!(info.manufacturer.as_deref(), Some("Google"));
+    assert_eq!(info.model.as_deref(), Some("Pixel 7"));
+    assert_eq!(info.abi_list, vec!["arm64-v8a", "armeabi-v7a", "armeabi"]);
+    assert_eq!(info.locale.as_deref(), Some("en-US"));
+    assert_eq!(adb.property("ro.empty").unwrap().as_deref(), Some(""));
+    assert_eq!(adb.with_timeout(Duration::from_secs(1)).property("ro.missing").unwrap(), None);
+    assert_eq!(runner.calls().len(), 1);
+
+    runner.respond_stdout(&getprop, "[ro.build.version.sdk]: [35]\n");
+    assert_eq!(adb.device_info().unwrap().sdk_level, Some(34));
+    adb.refresh_properties().unwrap();
+    assert_eq!(adb.device_info().unwrap().sdk_level, Some(35));
+    assert_eq!(runner.calls().len(), 2);
+
+    runner.respond_stdout(&getprop, "[ro.build.version.sdk]: [36]\n");
+    let other_server = adb.with_server_addr("127.0.0.1:5038".parse().unwrap());
+    assert_eq!(other_server.device_info().unwrap().sdk_level, Some(36));
+    assert_eq!(adb.device_info().unwrap().sdk_level, Some(35));
+    assert_eq!(runner.calls().len(), 3);
+}
+
+#[test]
+fn test_reboot_and_wait_for_recovery() {
+    let (runner, adb) = scripted_adb("emulator-5554");
+    runner.respond_stdout(
+        &["-s", "emulator-5554", "shell", "getprop"], "[ro.build.version.sdk]: [34]",
+    );
+    adb.properties().unwrap();
+    runner
+        .respond_stdout(&["devices", "-l"], "List of devices attached\nemulator-5554 device\n")
+        .respond_stdout(&["devices", "-l"], "List of devices attached\n")
+        .respond_stdout(&["devices", "-l"], "List of devices attached\nemulator-5554 recovery\n");
+    runner.clear_calls();
+    adb.reboot_and_wait(RebootMode::Recovery, Duration::from_secs(5)).unwrap();
+    let calls = runner.calls();
+    assert_eq!(calls[0], ["-s", "emulator-5554", "reboot", "recovery"]);
+    assert_eq!(calls.len(), 4);
+
+    let devices = |listing: &str| format!("List of devices attached\n{}", listing);
+    runner
+        .respond_stdout(&["devices", "-l"], &devices("emulator-5554 device\n"))
+        .respond_stdout(&["devices", "-l"], &devices(""))
+        .respond_stdout(&["devices", "-l"], &devices("emulator-5554 bootloader\n"));
+    runner.clear_calls();
+    let started = Instant::now();
+    adb.reboot_and_wait(RebootMode::Bootloader, Duration::from_secs(5)).unwrap();
+    assert_eq!(runner.calls()[0], ["-s", "emulator-5554", "reboot", "bootloader"]);
+    assert!(started.elapsed() < Duration::from_secs(2));
+
+    // A fastboot-only bootloader never shows up, which counts as arrived after the grace
+    // period of half the timeout.
+    runner
+        .respond_stdout(&["devices", "-l"], &devices("emulator-5554 device\n"))
+        .respond_stdout(&["devices", "-l"], &devices(""));
+    let started = Instant::now();
+    adb.reboot_and_wait(RebootMode::Bootloader, Duration::from_secs(1)).unwrap();
+    assert!(started.elapsed() >= Duration::from_millis(500));
+
+    adb.reboot(RebootMode::System).unwrap();
+    assert_eq!(runner.calls().last().unwrap(), &["-s", "emulator-5554", "reboot"]);
+    adb.properties().unwrap();
+    assert_eq!(runner.calls().last().unwrap(), &["-s", "emulator-5554", "shell", "getprop"]);
+}
+
+fn raw_screencap(
+    width: u32, height: u32, format: u32, colorspace: bool, pixels: &[u8],
+) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for word in [width, height, format] {
+        bytes.extend_from_slice(&word.to_le_bytes());
+    }
+    if colorspace {
+        bytes.extend_from_slice(&1u32.to_le_bytes());
+    }
+    bytes.extend_from_slice(pixels);
+    bytes
+}
+
+#[test]
+fn test_raw_screencap_capture_mode() {
+    let (runner, adb) = scripted_adb("emulator-5554");
+    let raw = ["-s", "emulator-5554", "exec-out", "screencap"];
+    runner.respond(&raw, CommandOutput::new(
+        raw_screencap(2, 1, 2, true, &[255, 0, 0, 7, 0, 0, 255, 9]), Vec::new(), Some(0),
+    ));
+    let adb = adb.with_capture_mode(CaptureMode::Raw);
+    let image = adb.capture_screen_as_rgba_image().unwrap();
+    assert_eq!(image.dimensions(), (2, 1));
+    assert_eq!(image.get_pixel(0, 0).0, [255, 0, 0, 255]);
+    assert_eq!(image.get_pixel(1, 0).0, [0, 0, 255, 255]);
+
+    // Pre-Android 9 header without colorspace, RGB_565 pure green then white.
+    runner.respond(&raw, CommandOutput::new(
+        raw_screencap(2, 1, 4, false, &[0xe0, 0x07, 0xff, 0xff]), Vec::new(), Some(0),
+    ));
+    let image = adb.capture_screen_as_dynamic_image().unwrap().into_rgba8();
+    assert_eq!(image.get_pixel(0, 0).0, [0, 255, 0, 255]);
+    assert_eq!(image.get_pixel(1, 0).0, [255, 255, 255, 255]);
+    assert_eq!(adb.capture_raw_screencap().unwrap().color_space, ColorSpace::Unknown);
+
+    let mut display_p3 = raw_screencap(1, 1, 1, false, &[]);
+    display_p3.extend_from_slice(&2u32.to_le_bytes());
+    display_p3.extend_from_slice(&[200, 30, 40, 255]);
+    runner.respond(&raw, CommandOutput::new(display_p3, Vec::new(), Some(0)));
+    let screencap = adb.with_capture_mode(CaptureMode::Png).capture_raw_screencap().unwrap();
+    assert_eq!(screencap.color_space, ColorSpace::DisplayP3);
+    assert_eq!(screencap.image.get_pixel(0, 0).0, [200, 30, 40, 255]);
+    runner.respond(&raw, CommandOutput::new(
+        raw_screencap(1, 1, 1, true, &[0; 4]), Vec::new(), Some(0),
+    ));
+    assert_eq!(adb.capture_raw_screencap().unwrap().color_space, ColorSpace::Srgb);
+
+    runner.respond(&raw, CommandOutput::new(
+        raw_screencap(2, 1, 1, true, &[0; 2]), Vec::new(), Some(0),
+    ));
+    assert!(matches!(adb.capture_screen_as_rgba_image(), Err(AdbError::UnparseableOutput(_))));
+    runner.respond(&raw, CommandOutput::new(
+        raw_screencap(1, 1, 3, true, &[0; 3]), Vec::new(), Some(0),
+    ));
+    assert!(matches!(adb.capture_screen_as_rgba_image(), Err(AdbError::UnparseableOutput(_))));
+}
+
+#[test]
+fn test_frame_stream_flags_changes_outside_masks() {
+    let (runner, adb) = scripted_adb("emulator-5554");
+    let raw = ["-s", "emulator-5554", "exec-out", "screencap"];
+    let frame = |first: u8, second: u8| CommandOutput::new(
+        raw_screencap(2, 1, 1, true, &[first, 0, 0, 255, second, 0, 0, 255]), Vec::new(), Some(0),
+    );
+    runner
+        .respond(&raw, frame(0, 0))
+        .respond(&raw, frame(0, 9))
+        .respond(&raw, frame(9, 9));
+    let options = FrameStreamOptions {
+        fps: 100.0,
+        buffer: 8,
+        masks: vec![Rect::new(1, 0, 1, 1)],
+    };
+    let mut frames = adb.with_capture_mode(CaptureMode::Raw).frame_stream(options).unwrap();
+    let changes: Vec<(u64, bool)> = frames
+        .by_ref()
+        .take(4)
+        .map(|frame| frame.map(|frame| (frame.sequence, frame.changed)).unwrap())
+        .collect();
+    assert_eq!(changes, [(0, true), (1, false), (2, true), (3, false)]);
+    frames.stop();
+
+    let options = FrameStreamOptions {
+        fps: 100.0,
+        buffer: 1,
+        masks: Vec::new(),
+    };
+    let frames = adb.with_capture_mode(CaptureMode::Raw).frame_stream(options).unwrap();
+    thread::sleep(Duration::from_millis(200));
+    assert_eq!(frames.try_recv().unwrap().unwrap().sequence, 0);
+    let next = frames.recv_timeout(Duration::from_secs(1)).unwrap().unwrap();
+    assert!(next.dropped > 0);
+    assert_eq!(next.sequence, next.dropped + 1);
+}
+
+#[test]
+fn test_screen_recording_pulls_segments() {
+    let files: FakeFs = Arc::new(Mutex::new(HashMap::new()));
+    let (runner, adb) = scripted_adb("emulator-5554");
+    let adb = adb.with_server_addr(fake_sync_server(files.clone()));
+    let recording = adb.start_screen_recording(ScreenRecordOptions {
+        bit_rate: Some(4_000_000),
+        size: Some((720, 1280)),
+        time_limit: Some(Duration::from_secs(30)),
+        ..ScreenRecordOptions::default()
+    }).unwrap();
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while recording.remote_segments().is_empty() && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(10));
+    }
+    let segments = recording.remote_segments();
+    assert_eq!(segments.len(), 1);
+    assert!(segments[0].starts_with("/data/local/tmp/dark_instruments-"));
+    assert!(segments[0].ends_with("-000.mp4"));
+    files.lock().unwrap()
+        .insert(segments[0].clone(), fake_file(0o100660, 1_700_000_000, b"ftypmp42"));
+
+    let local_dir = temp_dir("screen_recording");
+    let stopped = recording.stop(&local_dir);
+    assert!(stopped.is_complete());
+    let pulled = stopped.segments;
+    assert_eq!(pulled.len(), 1);
+    assert_eq!(fs::read(&pulled[0]).unwrap(), b"ftypmp42");
+    let calls = runner.calls();
+    assert!(calls.contains(&[
+        "-s", "emulator-5554", "shell", "screenrecord", "--bit-rate", "4000000",
+        "--size", "720x1280", "--time-limit", "30", &segments[0],
+    ].map(str::to_string).to_vec()));
+    assert_eq!(calls.last().unwrap(), &[
+        "-s", "emulator-5554", "shell", "rm", "-f", &segments[0],
+    ].map(str::to_string).to_vec());
+    fs::remove_dir_all(&local_dir).unwrap();
+}
+
+#[test]
+fn test_failed_screen_recording_still_pulls_segments() {
+    let files: FakeFs = Arc::new(Mutex::new(HashMap::new()));
+    let (runner, adb) = scripted_adb("emulator-5554");
+    let adb = adb.with_server_addr(fake_sync_server(files.clone()));
+    let options = ScreenRecordOptions {
+        time_limit: Some(Duration::from_secs(30)),
+        remote_dir: Some("/sdcard".to_string()),
+        ..ScreenRecordOptions::default()
+    };
+    // Segment names embed the start time, so fail every name the next second could produce.
+    let now = || SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis();
+    let started = now();
+    for millis in started..started + 1000 {
+        let remote = format!("/sdcard/dark_instruments-{}-000.mp4", millis);
+        runner.respond(
+            &["-s", "emulator-5554", "shell", "screenrecord", "--time-limit", "30", &remote],
+            CommandOutput::new(Vec::new(), b"encoder failed".to_vec(), Some(1)),
+        );
+    }
+    let wait_for_segment = |recording: &ScreenRecording| {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while recording.remote_segments().is_empty() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        recording.remote_segments()[0].clone()
+    };
+
+    let recording = adb.start_screen_recording(options.clone()).unwrap();
+    let segment = wait_for_segment(&recording);
+    files.lock().unwrap().insert(segment.clone(), fake_file(0o100660, 1_700_000_000, b"ftyp"));
+    let local_dir = temp_dir("failed_screen_recording");
+    let stopped = recording.stop(&local_dir);
+    assert!(!stopped.is_complete());
+    assert!(matches!(stopped.recording_error, Some(AdbError::NonZeroExit { .. })));
+    assert_eq!(stopped.segments.len(), 1);
+    assert_eq!(fs::read(&stopped.segments[0]).unwrap(), b"ftyp");
+    let rm = ["rm".to_string(), "-f".to_string(), segment];
+    assert!(runner.calls().last().unwrap().ends_with(&rm));
+    fs::remove_dir_all(&local_dir).unwrap();
+
+    // Nothing was written before the failure, so there is nothing to pull or delete. The
+    // pause gives the second recording a different segment name.
+    thread::sleep(Duration::from_millis(2));
+    runner.clear_calls();
+    let recording = adb.start_screen_recording(options).unwrap();
+    wait_for_segment(&recording);
+    let stopped = recording.stop(&local_dir);
+    assert!(stopped.recording_error.is_some());
+    assert!(stopped.segments.is_empty() && stopped.unpulled.is_empty());
+    assert!(runner.calls().iter().all(|call| !call.contains(&"rm".to_string())));
+}
+
+fn gradient_image(width: u32, height: u32) -> RgbaImage {
+    RgbaImage::from_fn(width, height, |x, y| {
+        let value = ((x * 255 / width + y * 128 / height) % 256) as u8;
+        Rgba([value, value / 2, 255 - value, 255])
+    })
+}
+
+#[test]
+fn test_perceptual_hash_ignores_exclusions() {
+    let screen = gradient_image(64, 128);
+    let mut clock_ticked = screen.clone();
+    for x in 40..60 {
+        for y in 0..8 {
+            clock_ticked.put_pixel(x, y, Rgba([255, 255, 255, 255]));
+        }
+    }
+    let mut inverted = screen.clone();
+    image::imageops::invert(&mut inverted);
+    let status_bar = [Rect::new(0, 0, 64, 8)];
+    let algorithms = [HashAlgorithm::Average, HashAlgorithm::Difference, HashAlgorithm::Perceptual];
+    for algorithm in algorithms {
+        let hash = |image: &RgbaImage, exclusions: &[Rect]| {
+            perceptual_hash(&DynamicImage::ImageRgba8(image.clone()), algorithm, exclusions)
+        };
+        let base = hash(&screen, &status_bar);
+        assert_eq!(base.distance(&hash(&clock_ticked, &status_bar)), 0, "{:?}", algorithm);
+        assert!(base.distance(&hash(&inverted, &status_bar)) > 32, "{:?}", algorithm);
+        assert_eq!(base.to_string().len(), 16);
+    }
+
+    let (runner, adb) = scripted_adb("emulator-5554");
+    let mut png = Vec::new();
+    DynamicImage::ImageRgba8(screen.clone())
+        .write_to(&mut io::Cursor::new(&mut png), image::ImageFormat::Png)
+        .unwrap();
+    runner.respond(
+        &["-s", "emulator-5554", "exec-out", "screencap", "-p"],
+        CommandOutput::new(png, Vec::new(), Some(0)),
+    );
+    let screen_hash = adb.screen_hash(HashAlgorithm::Perceptual, &status_bar).unwrap();
+    let expected = perceptual_hash(
+        &DynamicImage::ImageRgba8(screen), HashAlgorithm::Perceptual, &status_bar,
+    );
+    assert_eq!(screen_hash, expected);
+    assert_eq!(screen_hash.similarity(&expected), 1.0);
+}
+
+#[test]
+fn test_wait_until_stable_and_for_change() {
+    let (runner, adb) = scripted_adb("emulator-5554");
+    let adb = adb.with_capture_mode(CaptureMode::Raw);
+    let raw = ["-s", "emulator-5554", "exec-out", "screencap"];
+    let (width, height) = (270, 600);
+    let paint = |image: &mut RgbaImage, rect: Rect, color: Rgba<u8>| {
+        for y in rect.y..rect.bottom() {
+            for x in rect.x..rect.right() {
+                image.put_pixel(x, y, color);
+            }
+        }
+    };
+    let status_bar = [Rect::new(0, 0, width, 24)];
+    let settled = RgbaImage::from_pixel(width, height, Rgba([255, 255, 255, 255]));
+    let mut spinning = settled.clone();
+    paint(&mut spinning, Rect::new(119, 284, 24, 24), Rgba([0, 120, 215, 255]));
+    let mut clock_ticked = settled.clone();
+    paint(&mut clock_ticked, Rect::new(10, 4, 40, 16), Rgba([0, 0, 0, 255]));
+    // Capture noise on every pixel and a blinking cursor still count as the same screen.
+    let mut noisy = clock_ticked.clone();
+    for (x, y, pixel) in noisy.enumerate_pixels_mut() {
+        let channel = &mut pixel.0[(x + y) as usize % 3];
+        *channel = channel.saturating_sub(((x * 7 + y * 13) % 6) as u8);
+    }
+    paint(&mut noisy, Rect::new(60, 400, 2, 40), Rgba([0, 0, 0, 255]));
+    let frame = |image: &RgbaImage| CommandOutput::new(
+        raw_screencap(width, height, 1, true, image.as_raw()), Vec::new(), Some(0),
+    );
+    runner
+        .respond(&raw, frame(&spinning))
+        .respond(&raw, frame(&settled))
+        .respond(&raw, frame(&noisy));
+    let stable = adb
+        .wait_until_stable_excluding(
+            Duration::from_millis(200), &status_bar, Duration::from_secs(5),
+        )
+        .unwrap();
+    assert_eq!(stable.to_rgba8(), noisy);
+    assert_eq!(runner.calls().len(), 3);
+
+    // A toggle covering well under 1% of the screen still counts as a change.
+    let mut toggled = clock_ticked.clone();
+    paint(&mut toggled, Rect::new(200, 100, 20, 20), Rgba([0, 120, 215, 255]));
+    runner
+        .respond(&raw, frame(&settled))
+        .respond(&raw, frame(&toggled));
+    let changed = adb
+        .wait_for_change_excluding(&stable, &status_bar, Duration::from_secs(5))
+        .unwrap();
+    assert_eq!(changed.to_rgba8(), toggled);
+    assert!(matches!(
+        adb.wait_for_change(&changed, Duration::from_millis(300)),
+        Err(AdbError::Timeout(_))
+    ));
+    runner.respond(&raw, frame(&clock_ticked));
+    assert!(adb.wait_for_change(&changed, Duration::from_millis(300)).is_ok());
+    let lenient = adb.with_similarity_threshold(0.99);
+    assert!(matches!(
+        lenient.wait_for_change(&changed, Duration::from_millis(300)),
+        Err(AdbError::Timeout(_))
+    ));
+}
+
+#[test]
+fn test_rect_helpers() {
+    let screen = Rect::new(0, 0, 1080, 2400);
+    let button = Rect::from_corners(Point::new(100, 200), Point::new(300, 260));
+    assert_eq!(button, Rect::new(100, 200, 200, 60));
+    assert_eq!(button.center(), Point::new(200, 230));
+    assert_eq!(button.bottom_right(), Point::new(300, 260));
+    assert!(screen.contains_rect(&button));
+    assert!(button.contains_point(Point::new(100, 200)));
+    assert!(!button.contains_point(button.bottom_right()));
+    assert_eq!(
+        button.intersection(&Rect::new(250, 0, 500, 210)),
+        Some(Rect::new(250, 200, 50, 10)),
+    );
+    assert!(!button.intersects(&Rect::new(300, 200, 10, 10)));
+    assert_eq!(Point::new(5, 5).offset(button.x, button.y), Point::new(105, 205));
+    assert_eq!(Point::new(u32::MAX - 1, 5).offset(10, 10), Point::new(u32::MAX, 15));
+    assert_eq!(Rect::new(u32::MAX - 1, 0, 10, 10).center(), Point::new(u32::MAX, 5));
+}
+
+#[test]
+fn test_capture_region_clips_to_screen() {
+    let (runner, adb) = scripted_adb("emulator-5554");
+    let adb = adb.with_capture_mode(CaptureMode::Raw);
+    let screen = gradient_image(32, 64);
+    runner.respond(&["-s", "emulator-5554", "exec-out", "screencap"], CommandOutput::new(
+        raw_screencap(32, 64, 1, true, screen.as_raw()), Vec::new(), Some(0),
+    ));
+    let region = adb.capture_region(Rect::new(24, 60, 16, 16)).unwrap().to_rgba8();
+    assert_eq!(region.dimensions(), (8, 4));
+    assert_eq!(region.get_pixel(0, 0), screen.get_pixel(24, 60));
+    assert!(matches!(
+        adb.capture_region(Rect::new(40, 0, 8, 8)),
+        Err(AdbError::RegionOutOfBounds(_))
+    ));
+}
+
+/// A deterministic pseudo-random texture, so templates cut from it match in one place only.
+fn noise_image(width: u32, height: u32, seed: u32) -> RgbaImage {
+    RgbaImage::from_fn(width, height, |x, y| {
+        let mut value = x.wrapping_mul(374_761_393) ^ y.wrapping_mul(668_265_263) ^ seed;
+        value = (value ^ (value >> 13)).wrapping_mul(1_274_126_177);
+        let value = (value >> 24) as u8;
+        Rgba([value, value, value, 255])
+    })
+}
+
+#[test]
+fn test_find_template_at_multiple_scales() {
+    let icon = image::imageops::blur(&noise_image(40, 40, 7), 1.5);
+    let mut screen = noise_image(400, 300, 1);
+    image::imageops::overlay(&mut screen, &icon, 30, 50);
+    image::imageops::overlay(&mut screen, &icon, 250, 200);
+    let large_icon = image::imageops::resize(&icon, 60, 60, image::imageops::FilterType::Triangle);
+    image::imageops::overlay(&mut screen, &large_icon, 200, 20);
+    let screen = DynamicImage::ImageRgba8(screen);
+    let template = DynamicImage::ImageRgba8(icon);
+
+    let matches = find_template(&screen, &template, &MatchOptions::default());
+    let rects: Vec<Rect> = matches.iter().map(|found| found.rect).collect();
+    assert_eq!(rects.len(), 2);
+    assert!(rects.contains(&Rect::new(30, 50, 40, 40)));
+    assert!(rects.contains(&Rect::new(250, 200, 40, 40)));
+    assert!(matches.iter().all(|found| found.score > 0.99));
+
+    let options = MatchOptions {
+        scales: vec![1.0, 1.5],
+        region: Some(Rect::new(150, 0, 250, 150)),
+        ..MatchOptions::default()
+    };
+    let matches = find_template(&screen, &template, &options);
+    assert_eq!(matches.len(), 1);
+    assert_eq!(matches[0].rect, Rect::new(200, 20, 60, 60));
+    assert_eq!(matches[0].scale, 1.5);
+
+    let (runner, adb) = scripted_adb("emulator-5554");
+    let adb = adb.with_capture_mode(CaptureMode::Raw);
+    let raw = screen.to_rgba8();
+    runner.respond(&["-s", "emulator-5554", "exec-out", "screencap"], CommandOutput::new(
+        raw_screencap(400, 300, 1, true, raw.as_raw()), Vec::new(), Some(0),
+    ));
+    let best = adb.find_best_on_screen(&template, &MatchOptions::default()).unwrap().unwrap();
+    assert!(best.rect == Rect::new(30, 50, 40, 40) || best.rect == Rect::new(250, 200, 40, 40));
+}
+
+#[test]
+fn test_find_template_reports_every_repeated_icon() {
+    let icon = image::imageops::blur(&noise_image(32, 32, 7), 1.5);
+    let mut screen = noise_image(480, 384, 1);
+    for row in 0..8 {
+        for column in 0..10 {
+            image::imageops::overlay(&mut screen, &icon, column * 48 + 8, row * 48 + 8);
+        }
+    }
+    let matches = find_template(
+        &DynamicImage::ImageRgba8(screen), &DynamicImage::ImageRgba8(icon),
+        &MatchOptions::default(),
+    );
+    assert_eq!(matches.len(), 80);
+    for row in 0..8 {
+        for column in 0..10 {
+            let rect = Rect::new(column * 48 + 8, row * 48 + 8, 32, 32);
+            assert!(matches.iter().any(|found| found.rect == rect), "missing {:?}", rect);
+        }
+    }
+}
+
+#[test]
+fn test_color_probes_and_tolerances() {
+    let red = Color::new(220, 30, 40);
+    let pressed_red = Color::new(180, 20, 30);
+    assert_eq!(Color::new(255, 0, 0).to_hsv(), Hsv { hue: 0.0, saturation: 1.0, value: 1.0 });
+    assert!(!ColorTolerance::Rgb(30.0).matches(&red, &pressed_red));
+    let hsv = ColorTolerance::Hsv { hue: 10.0, saturation: 0.1, value: 0.2 };
+    assert!(hsv.matches(&red, &pressed_red));
+    assert!(hsv.matches(&Color::new(255, 0, 10), &Color::new(255, 10, 0)));
+    assert!(!hsv.matches(&red, &Color::new(30, 200, 40)));
+
+    let mut screen = RgbaImage::from_pixel(20, 10, Rgba([0, 0, 0, 255]));
+    for x in 10..20 {
+        for y in 0..10 {
+            screen.put_pixel(x, y, Rgba([200, 100, 0, 255]));
+        }
+    }
+    let screen = DynamicImage::ImageRgba8(screen);
+    assert_eq!(pixel_at(&screen, Point::new(12, 3)), Some(Color::new(200, 100, 0)));
+    assert_eq!(pixel_at(&screen, Point::new(20, 3)), None);
+    assert_eq!(average_color(&screen, Rect::new(5, 0, 10, 10)), Some(Color::new(100, 50, 0)));
+    let signature = ColorSignature::new(
+        vec![(Point::new(1, 1), Color::new(0, 0, 0)), (Point::new(15, 5), Color::new(205, 98, 0))],
+        ColorTolerance::Rgb(10.0),
+    );
+    assert!(signature.matches(&screen));
+
+    let (runner, adb) = scripted_adb("emulator-5554");
+    let adb = adb.with_capture_mode(CaptureMode::Raw);
+    let raw = ["-s", "emulator-5554", "exec-out", "screencap"];
+    let frame = |image: &RgbaImage| CommandOutput::new(
+        raw_screencap(20, 10, 1, true, image.as_raw()), Vec::new(), Some(0),
+    );
+    runner
+        .respond(&raw, frame(&RgbaImage::from_pixel(20, 10, Rgba([0, 0, 0, 255]))))
+        .respond(&raw, frame(&screen.to_rgba8()));
+    let matched = adb.wait_for_color(
+        Point::new(15, 5),
+        Color::new(200, 100, 0),
+        ColorTolerance::Rgb(5.0),
+        Duration::from_secs(5),
+    ).unwrap();
+    assert_eq!(matched.to_rgba8(), screen.to_rgba8());
+    assert_eq!(adb.pixel_at(Point::new(0, 0)).unwrap(), Color::new(0, 0, 0));
+    assert!(adb.matches_signature(&signature).unwrap());
+}
+
+#[test]
+fn test_diff_images_with_masks_and_anti_aliasing() {
+    let white = Rgba([255, 255, 255, 255]);
+    let baseline = RgbaImage::from_fn(40, 30, |x, _| match x < 10 {
+        true => Rgba([0, 0, 0, 255]),
+        false => white,
+    });
+    let mut actual = baseline.clone();
+    for y in 0..30 {
+        actual.put_pixel(10, y, Rgba([128, 128, 128, 255]));
+        actual.put_pixel(39, y, Rgba([250, 252, 255, 255]));
+    }
+    let mut fill = |rect: Rect, color: Rgba<u8>| {
+        for y in rect.y..rect.bottom() {
+            for x in rect.x..rect.right() {
+                actual.put_pixel(x, y, color);
+            }
+        }
+    };
+    fill(Rect::new(20, 5, 4, 3), Rgba([255, 0, 0, 255]));
+    fill(Rect::new(26, 6, 2, 1), Rgba([255, 0, 0, 255]));
+    fill(Rect::new(30, 20, 3, 5), Rgba([0, 0, 255, 255]));
+    fill(Rect::new(0, 25, 5, 5), white);
+    let (baseline, actual) = (DynamicImage::ImageRgba8(baseline), DynamicImage::ImageRgba8(actual));
+
+    let options = DiffOptions {
+        tolerance: 8,
+        masks: vec![Rect::new(0, 25, 5, 5)],
+        ignore_anti_aliasing: true,
+    };
+    let diff = diff_images(&baseline, &actual, &options);
+    assert_eq!(diff.compared_pixels, 40 * 30 - 25);
+    assert_eq!(diff.mismatched_pixels, 12 + 2 + 15);
+    assert!((diff.mismatch_percentage() - 29.0 * 100.0 / 1175.0).abs() < 1e-9);
+    assert_eq!(diff.regions, vec![Rect::new(20, 5, 8, 3), Rect::new(30, 20, 3, 5)]);
+    assert_eq!(*diff.diff_image.get_pixel(21, 6), Rgba([255, 0, 0, 255]));
+    assert_eq!(*diff.diff_image.get_pixel(10, 0), Rgba([255, 200, 0, 255]));
+    assert!(diff_images(&baseline, &baseline, &options).is_match());
+
+    let strict = DiffOptions { ignore_anti_aliasing: false, ..options };
+    assert_eq!(diff_images(&baseline, &actual, &strict).mismatched_pixels, 29 + 30);
+    let cropped = baseline.crop_imm(0, 0, 40, 20);
+    assert_eq!(diff_images(&cropped, &baseline, &strict).mismatched_pixels, 40 * 10 - 25);
+
+    let (runner, adb) = scripted_adb("emulator-5554");
+    let adb = adb.with_capture_mode(CaptureMode::Raw);
+    runner.respond(&["-s", "emulator-5554", "exec-out", "screencap"], CommandOutput::new(
+        raw_screencap(40, 30, 1, true, actual.to_rgba8().as_raw()), Vec::new(), Some(0),
+    ));
+    let (screen, diff) = adb.diff_screen(&baseline, &strict).unwrap();
+    assert_eq!(screen.to_rgba8(), actual.to_rgba8());
+    let prefix = std::env::temp_dir().join(format!("dark_instruments_diff_{}", std::process::id()));
+    let paths = diff.save(&baseline, &screen, prefix.to_str().unwrap()).unwrap();
+    assert!(paths[2].ends_with(format!("dark_instruments_diff_{}_diff.png", std::process::id())));
+    for path in &paths {
+        assert!(path.exists());
+        fs::remove_file(path).unwrap();
+    }
+}
+
+const GETEVENT_P: &str = "\
+add device 1: /dev/input/event1
+  name:     \"gpio-keys\"
+  events:
+    KEY (0001): 0072  0073  0074
+  input props:
+    <none>
+add device 2: /dev/input/event4
+  name:     \"fts_ts\"
+  events:
+    KEY (0001): 008b  009e  00ac
+                014a  0145
+    ABS (0003): 002f  : value 0, min 0, max 9, fuzz 0, flat 0, resolution 0
+                0035  : value 0, min 0, max 1079, fuzz 0, flat 0, resolution 0
+                0036  : value 0, min 0, max 2399, fuzz 0, flat 0, resolution 0
+                0039  : value 0, min 0, max 65535, fuzz 0, flat 0, resolution 0
+                003a  : value 0, min 0, max 255, fuzz 0, flat 0, resolution 0
+  input props:
+    INPUT_PROP_DIRECT
+";
+
+#[test]
+fn test_gesture_paths_and_execution() {
+    let path = PointerPath::new(Point::new(0, 0))
+        .move_to(Point::new(100, 0), Duration::from_millis(100))
+        .hold(Duration::from_millis(50));
+    assert_eq!(path.end(), Duration::from_millis(150));
+    assert_eq!(path.position_at(Duration::from_millis(25)), Some(Point::new(25, 0)));
+    assert_eq!(path.position_at(Duration::from_millis(120)), Some(Point::new(100, 0)));
+    assert_eq!(path.position_at(Duration::from_millis(151)), None);
+    let path = Gesture::path(
+        &[Point::new(0, 0), Point::new(30, 0), Point::new(30, 10)],
+        Duration::from_millis(400),
+    );
+    assert_eq!(path.pointers()[0].waypoints()[1], (Duration::from_millis(300), Point::new(30, 0)));
+    let rotate = Gesture::rotate(Point::new(500, 500), 100, 0.0, 90.0, Duration::from_secs(1));
+    assert_eq!(rotate.pointers()[0].waypoints().last().unwrap().1, Point::new(500, 600));
+    assert_eq!(rotate.pointers()[1].waypoints().last().unwrap().1, Point::new(500, 400));
+
+    let files: FakeFs = Arc::new(Mutex::new(HashMap::new()));
+    let (runner, adb) = scripted_adb("emulator-5554");
+    let adb = adb.with_server_addr(fake_sync_server(files.clone()));
+    let getprop = ["-s", "emulator-5554", "shell", "getprop"];
+    runner
+        .respond_stdout(&getprop, "[ro.build.version.sdk]: [33]")
+        .respond_stdout(&["-s", "emulator-5554", "shell", "getevent", "-p"], GETEVENT_P)
+        .respond_stdout(&["-s", "emulator-5554", "shell", "dumpsys", "display"], DUMPSYS_DISPLAY);
+    let touchscreen = adb.touchscreen().unwrap();
+    assert_eq!(touchscreen.path, "/dev/input/event4");
+    assert_eq!(touchscreen.name.as_deref(), Some("fts_ts"));
+    assert_eq!((touchscreen.x_range, touchscreen.y_range), ((0, 1079), (0, 2399)));
+    assert_eq!((touchscreen.max_slot, touchscreen.pressure_range), (Some(9), Some((0, 255))));
+    assert!(touchscreen.has_btn_touch);
+
+    // Scripts are pushed, run with `sh` and deleted again.
+    let script = |runner: &ScriptedRunner| {
+        let calls = runner.calls();
+        let (run, cleanup) = (&calls[calls.len() - 2], &calls[calls.len() - 1]);
+        assert_eq!(run[3..4], ["sh"]);
+        assert!(run[4].starts_with("/data/local/tmp/dark_instruments-gesture-"));
+        assert_eq!(cleanup[3..], ["rm", "-f", &run[4]]);
+        String::from_utf8(files.lock().unwrap()[&run[4]].data.clone()).unwrap()
+    };
+    let swipe = [Point::new(10, 20), Point::new(110, 20)];
+    let swipe = Gesture::path(&swipe, Duration::from_millis(150));
+    adb.perform_gesture(&swipe).unwrap();
+    assert_eq!(script(&runner), "input motionevent DOWN 10 20\nsleep 0.100\n\
+input motionevent MOVE 77 20\nsleep 0.050\ninput motionevent MOVE 110 20\n\
+input motionevent UP 110 20");
+
+    // The display is rotated to landscape with a 720x1600 override, the panel is 1080x2400.
+    // Only the slot selection and axes that change are sent.
+    let pinch = Gesture::pinch(Point::new(800, 360), 200, 400, Duration::from_millis(50));
+    adb.perform_gesture(&pinch).unwrap();
+    let event = |code: u16, value: i32| format!("sendevent /dev/input/event4 3 {} {}", code, value);
+    let expected = [
+        event(0x2f, 0), event(0x39, 0), event(0x35, 538), event(0x36, 1050), event(0x3a, 128),
+        event(0x2f, 1), event(0x39, 1), event(0x35, 538), event(0x36, 1350), event(0x3a, 128),
+        "sendevent /dev/input/event4 1 330 1".to_string(),
+        "sendevent /dev/input/event4 0 0 0".to_string(),
+        "sleep 0.033".to_string(),
+        event(0x2f, 0), event(0x36, 951), event(0x2f, 1), event(0x36, 1449),
+        "sendevent /dev/input/event4 0 0 0".to_string(),
+        "sleep 0.017".to_string(),
+        event(0x2f, 0), event(0x36, 900), event(0x2f, 1), event(0x36, 1500),
+        "sendevent /dev/input/event4 0 0 0".to_string(),
+        event(0x2f, 0), event(0x39, -1), event(0x2f, 1), event(0x39, -1),
+        "sendevent /dev/input/event4 1 330 0".to_string(),
+        "sendevent /dev/input/event4 0 0 0".to_string(),
+    ];
+    assert_eq!(script(&runner), expected.join("\n"));
+}
+
+const DUMPSYS_DISPLAY: &str = "\
+DISPLAY MANAGER (dumpsys display)
+Display Devices: size=2
+  DisplayDeviceInfo{\"Built-in Screen\": uniqueId=\"local:4619827259835644672\", 1080 x 2400, \
+modeId 2, defaultModeId 1, supportedModes [{id=1, width=1080, height=2400, fps=60.0, \
+alternativeRefreshRates=[90.0]}, {id=2, width=1080, height=2400, fps=90.0, \
+alternativeRefreshRates=[60.0]}], colorMode 0, density 420, 409.432 x 411.891 dpi, \
+rotation 0, type INTERNAL, state ON, FLAG_ALLOWED_TO_BE_DEFAULT_DISPLAY}
+  DisplayDeviceInfo{\"HDMI Screen\": uniqueId=\"local:1\", 1920 x 1080, modeId 1, \
+supportedModes [{id=1, width=1920, height=1080, fps=60.0}], density 320, type EXTERNAL}
+Logical Displays: size=2
+  Display 0:
+    mDisplayId=0
+    mBaseDisplayInfo=DisplayInfo{\"Built-in Screen\", displayId 0, displayGroupId 0, \
+FLAG_SECURE, real 1080 x 2400, largest app 2400 x 2337, smallest app 1080 x 1017, mode 2, \
+defaultMode 1, modes [{id=1, width=1080, height=2400, fps=60.0, alternativeRefreshRates=[90.0]}, \
+{id=2, width=1080, height=2400, fps=90.0, alternativeRefreshRates=[60.0]}], rotation 0, \
+state ON, type INTERNAL, uniqueId \"local:4619827259835644672\", app 1080 x 2400, density 420 \
+(409.432 x 411.891) dpi, layerStack 0, cutout DisplayCutout{insets=Rect(0, 136 - 0, 0) \
+waterfall=Insets{left=0, top=0, right=0, bottom=0}}, installOrientation ROTATION_0}
+    mOverrideDisplayInfo=DisplayInfo{\"Built-in Screen\", displayId 0, displayGroupId 0, \
+FLAG_SECURE, real 1600 x 720, largest app 1600 x 1557, smallest app 720 x 677, mode 2, \
+defaultMode 1, modes [{id=1, width=1080, height=2400, fps=60.0, alternativeRefreshRates=[90.0]}, \
+{id=2, width=1080, height=2400, fps=90.0, alternativeRefreshRates=[60.0]}], rotation 1, \
+state ON, type INTERNAL, uniqueId \"local:4619827259835644672\", app 1600 x 720, density 360 \
+(409.432 x 411.891) dpi, layerStack 0, cutout DisplayCutout{insets=Rect(91, 0 - 0, 0) \
+waterfall=Insets{left=0, top=0, right=0, bottom=0}}, installOrientation ROTATION_0}
+  Display 2:
+    mDisplayId=2
+    mBaseDisplayInfo=DisplayInfo{\"HDMI Screen\", displayId 2, real 1920 x 1080, mode 1, \
+modes [{id=1, width=1920, height=1080, fps=60.0}], rotation 0, type EXTERNAL, \
+uniqueId \"local:1\", density 320 (160.0 x 160.0) dpi, cutout null}
+    mOverrideDisplayInfo=null
+";
+
+#[test]
+fn test_display_info_from_dumpsys_and_wm() {
+    let (runner, adb) = scripted_adb("emulator-5554");
+    runner
+        .respond_stdout(&["-s", "emulator-5554", "shell", "dumpsys", "display"], DUMPSYS_DISPLAY)
+        .respond_stdout(
+            &["-s", "emulator-5554", "shell", "wm", "size"],
+            "Physical size: 1080x2400\nOverride size: 720x1600",
+        )
+        .respond_stdout(
+            &["-s", "emulator-5554", "shell", "wm", "density"],
+            "Physical density: 420\nOverride density: 360",
+        );
+    let displays = adb.display_info().unwrap();
+    assert_eq!(displays.len(), 2);
+    let built_in = &displays[0];
+    assert_eq!(built_in.display_id, 0);
+    assert_eq!(built_in.name.as_deref(), Some("Built-in Screen"));
+    assert_eq!(built_in.physical_size, DisplaySize { width: 1080, height: 2400 });
+    assert_eq!(built_in.size(), DisplaySize { width: 720, height: 1600 });
+    assert_eq!((built_in.physical_density, built_in.density()), (Some(420), Some(360)));
+    assert_eq!(built_in.rotation, Rotation::Rotation90);
+    assert_eq!(built_in.refresh_rate, Some(90.0));
+    assert_eq!(built_in.cutout, Some(Insets { left: 91, top: 0, right: 0, bottom: 0 }));
+
+    // Without per-display `wm` support the dumpsys values are used.
+    let external = adb.display_info_of(2).unwrap();
+    assert_eq!(external.name.as_deref(), Some("HDMI Screen"));
+    assert_eq!(external.size(), DisplaySize { width: 1920, height: 1080 });
+    assert_eq!((external.override_size, external.density()), (None, Some(320)));
+    assert_eq!((external.refresh_rate, external.cutout), (Some(60.0), None));
+    assert!(runner.calls().contains(
+        &["-s", "emulator-5554", "shell", "wm", "size", "-d", "2"].map(str::to_string).to_vec()
+    ));
+}
+
+#[test]
+fn test_screen_transform_for_all_rotations() {
+    let natural_size = DisplaySize { width: 1080, height: 2400 };
+    let top_left = Point::new(0, 0);
+    let expected = [
+        (Rotation::Rotation0, Point::new(0, 0)),
+        (Rotation::Rotation90, Point::new(0, 1079)),
+        (Rotation::Rotation180, Point::new(1079, 2399)),
+        (Rotation::Rotation270, Point::new(2399, 0)),
+    ];
+    for (rotation, rotated_top_left) in expected {
+        let mut transform = ScreenTransform::new(rotation, natural_size);
+        assert_eq!(transform.natural_to_rotated(top_left), rotated_top_left, "{:?}", rotation);
+        let point = Point::new(100, 2000);
+        assert_eq!(transform.rotated_to_natural(transform.natural_to_rotated(point)), point);
+        assert_eq!(transform.screenshot_to_input(point), point);
+        transform.screenshot_space = CoordinateSpace::Natural;
+        assert_eq!(transform.screenshot_to_input(point), transform.natural_to_rotated(point));
+        assert_eq!(transform.input_to_screenshot(transform.screenshot_to_input(point)), point);
+    }
+    let sideways = ScreenTransform::new(Rotation::Rotation90, natural_size);
+    assert_eq!(sideways.rotated_size(), DisplaySize { width: 2400, height: 1080 });
+}
+
+#[test]
+fn test_rotation_settings_and_detected_transform() {
+    let (runner, adb) = scripted_adb("emulator-5554");
+    adb.set_rotation(Rotation::Rotation270).unwrap();
+    let settings: Vec<String> = runner.calls()
+        .iter()
+        .map(|call| call[6..].join(" "))
+        .collect();
+    assert_eq!(settings, ["accelerometer_rotation 0", "user_rotation 3"]);
+
+    runner
+        .respond_stdout(&["-s", "emulator-5554", "shell", "dumpsys", "display"], DUMPSYS_DISPLAY)
+        .respond_stdout(
+            &["-s", "emulator-5554", "shell", "wm", "size"],
+            "Physical size: 1080x2400\nOverride size: 720x1600",
+        );
+    assert_eq!(adb.rotation().unwrap(), Rotation::Rotation90);
+    let landscape = DynamicImage::new_rgba8(1600, 720);
+    let transform = adb.screen_transform(&landscape).unwrap();
+    assert_eq!(transform.screenshot_space, CoordinateSpace::Rotated);
+    assert_eq!(transform.screenshot_to_input(Point::new(10, 20)), Point::new(10, 20));
+    let portrait = DynamicImage::new_rgba8(720, 1600);
+    let transform = adb.screen_transform(&portrait).unwrap();
+    assert_eq!(transform.screenshot_space, CoordinateSpace::Natural);
+    assert_eq!(transform.screenshot_to_input(Point::new(10, 20)), Point::new(20, 709));
+}
@@ -0,0 +1,162 @@
+use std::collections::HashMap;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::mpsc;
+use std::sync::mpsc::{Receiver, Sender};
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use crate::adb::server_error;
+use crate::device::{parse_devices, Device, DeviceState};
+use crate::error::AdbError;
+use crate::runner::{CancellationToken, RunOptions};
+use crate::server::{server_failure, ServerConnection};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum DeviceEvent {
+    Added(Device),
+    Removed(Device),
+    StateChanged {
+        device: Device,
+        previous: DeviceState,
+    },
+}
+
+/// A subscription to device hotplug events, backed by `host:track-devices` on a background
+/// thread.
+///
+/// Devices already connected when tracking starts are reported as [`DeviceEvent::Added`].
+/// The iterator ends after the server connection fails, yielding the error last. Dropping
+/// the tracker or calling [`DeviceTracker::stop`] closes the connection.
+pub struct DeviceTracker {
+    receiver: Receiver<Result<DeviceEvent, AdbError>>,
+    cancellation: CancellationToken,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl DeviceTracker {
+    pub(crate) fn start(addr: SocketAddr) -> Result<Self, AdbError> {
+        let cancellation = CancellationToken::new();
+        let options = RunOptions {
+            timeout: None,
+            cancellation: Some(cancellation.clone()),
+        };
+        let connection = Self::connect(addr, &options)
+            .map_err(|err| server_error(err, &options))?;
+        let (sender, receiver) = mpsc::channel();
+        let handle = thread::Builder::new()
+            .name("adb-track-devices".to_string())
+            .spawn(move || Self::run(connection, options, sender))
+            .map_err(AdbError::Io)?;
+        Ok(DeviceTracker {
+            receiver,
+            cancellation,
+            handle: Some(handle),
+        })
+    }
+
+    fn connect(addr: SocketAddr, options: &RunOptions) -> io::Result<ServerConnection> {
+        let mut connection = ServerConnection::connect(addr, options)?;
+        match connection.request("host:track-devices-l") {
+            Err(err) if server_failure(&err).is_some() => {
+                // Servers predating the long listing only offer the plain variant.
+                let mut connection = ServerConnection::connect(addr, options)?;
+                connection.request("host:track-devices")?;
+                Ok(connection)
+            }
+            result => result.map(|_| connection),
+        }
+    }
+
+    fn run(
+        mut connection: ServerConnection,
+        options: RunOptions,
+        sender: Sender<Result<DeviceEvent, AdbError>>,
+    ) {
+        let mut known: HashMap<String, Device> = HashMap::new();
+        loop {
+            let listing = match connection.read_length_prefixed() {
+                Ok(listing) => listing,
+                Err(_) if options.is_cancelled() => return,
+                Err(err) => {
+                    let _ = sender.send(Err(server_error(err, &options)));
+                    return;
+                }
+            };
+            for event in diff_devices(&mut known, parse_devices(&listing)) {
+                if sender.send(Ok(event)).is_err() {
+                    return;
+                }
+            }
+        }
+    }
+
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<Result<DeviceEvent, AdbError>> {
+        self.receiver.recv_timeout(timeout).ok()
+    }
+
+    pub fn try_recv(&self) -> Option<Result<DeviceEvent, AdbError>> {
+        self.receiver.try_recv().ok()
+    }
+
+    pub fn stop(mut self) {
+        self.shutdown();
+    }
+
+    fn shutdown(&mut self) {
+        self.cancellation.cancel();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Iterator for DeviceTracker {
+    type Item = Result<DeviceEvent, AdbError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.receiver.recv().ok()
+    }
+}
+
+impl Drop for DeviceTracker {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+fn diff_devices(known: &mut HashMap<String, Device>, current: Vec<Device>) -> Vec<DeviceEvent> {
+    let mut events = Vec::new();
+    let mut current: HashMap<String, Device> = current
+        .into_iter()
+        .map(|device| (device.serial.clone(), device))
+        .collect();
+    let mut removed: Vec<String> = known
+        .keys()
+        .filter(|serial| !current.contains_key(*serial))
+        .cloned()
+        .collect();
+    removed.sort();
+    for serial in removed {
+        if let Some(device) = known.remove(&serial) {
+            events.push(DeviceEvent::Removed(device));
+        }
+    }
+    let mut serials: Vec<String> = current.keys().cloned().collect();
+    serials.sort();
+    for serial in serials {
+        let device = current.remove(&serial).unwrap();
+        match known.insert(serial, device.clone()) {
+            None => events.push(DeviceEvent::Added(device)),
+            Some(previous) if previous.state != device.state => {
+                events.push(DeviceEvent::StateChanged {
+                    device,
+                    previous: previous.state,
+                });
+            }
+            Some(_) => {}
+        }
+    }
+    events
+}